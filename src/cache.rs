@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use log::*;
+use serde::*;
+
+use crate::error::{Error, Result};
+
+/// FNV-1a, stable across toolchains unlike `DefaultHasher`.
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub size: u64,
+    pub mtime: u64,
+    pub hash: u64,
+}
+
+impl Fingerprint {
+    pub fn of<P: AsRef<Path>>(path: P) -> std::io::Result<Fingerprint> {
+        let path = path.as_ref();
+        let (size, mtime) = Fingerprint::stat(path)?;
+        Ok(Fingerprint {
+            size,
+            mtime,
+            hash: Fingerprint::hash(path)?,
+        })
+    }
+
+    fn stat(path: &Path) -> std::io::Result<(u64, u64)> {
+        let meta = std::fs::metadata(path)?;
+        let mtime = meta.modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok((meta.len(), mtime))
+    }
+
+    fn hash(path: &Path) -> std::io::Result<u64> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+        let mut buf = vec![0; 1 << 16];
+        loop {
+            match file.read(&mut buf)? {
+                0 => break,
+                n => hasher.write(&buf[..n]),
+            }
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Whether `path` still has this content; the hash is only checked when size or mtime moved.
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        match Fingerprint::stat(path) {
+            Ok((size, mtime)) if size == self.size && mtime == self.mtime => true,
+            Ok((size, _)) if size == self.size => Fingerprint::hash(path).map(|x| x == self.hash).unwrap_or(false),
+            _ => false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub settings: Vec<String>,
+    pub tool_version: String,
+    pub inputs: Vec<(String, Fingerprint)>,
+    pub output: Fingerprint,
+}
+
+/// One output file together with everything it is built from.
+pub struct Job<'a> {
+    pub output: &'a Path,
+    pub tool: &'a str,
+    pub command: Vec<String>,
+    /// Configuration shaping the output besides the command line, as `key=value`.
+    pub settings: Vec<String>,
+    pub inputs: Vec<PathBuf>,
+}
+
+/// Manifest of produced files, stored as `rz_build/cache.json`.
+///
+/// An output is reused only if it is unchanged since it was produced and its command line,
+/// settings, tool version and inputs are the same as when it was recorded.
+pub struct Cache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, Entry>>,
+    versions: Mutex<HashMap<String, String>>,
+}
+
+impl Cache {
+    pub fn load<P: Into<PathBuf>>(path: P) -> Result<Cache> {
+        let path = path.into();
+        let entries = if std::fs::metadata(&path).is_ok() {
+            std::fs::read_to_string(&path)
+                .map_err(Error::io(format!("failed to read {}", path.display())))
+                .map(|mut x| simd_json::serde::from_str(&mut x).unwrap_or_else(|e| {
+                    warn!("discarding unreadable cache manifest {}: {}", path.display(), e);
+                    HashMap::new()
+                }))?
+        } else {
+            HashMap::new()
+        };
+        Ok(Cache {
+            path,
+            entries: Mutex::new(entries),
+            versions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn store(&self) -> Result<()> {
+        let entries = self.entries.lock().unwrap();
+        serde_json::to_string(&*entries)
+            .map_err(|e| Error::Parse(format!("failed to serialize cache manifest: {}", e)))
+            .and_then(|x| std::fs::write(&self.path, x)
+                .map_err(Error::io(format!("failed to store {}", self.path.display()))))
+    }
+
+    fn version(&self, tool: &str) -> String {
+        if let Some(v) = self.versions.lock().unwrap().get(tool) {
+            return v.clone();
+        }
+        let version = Command::new(tool)
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map(|x| String::from_utf8_lossy(&x.stdout).trim().to_string())
+            .unwrap_or_default();
+        self.versions.lock().unwrap().insert(String::from(tool), version.clone());
+        version
+    }
+
+    pub fn is_fresh(&self, job: &Job) -> bool {
+        let entries = self.entries.lock().unwrap();
+        let entry = match entries.get(job.output.to_string_lossy().as_ref()) {
+            Some(entry) => entry.clone(),
+            None => return false
+        };
+        drop(entries);
+        entry.command == job.command
+            && entry.settings == job.settings
+            && entry.inputs.len() == job.inputs.len()
+            && entry.inputs.iter().zip(&job.inputs)
+            .all(|((path, print), input)| Path::new(path) == input && print.matches(input))
+            && entry.output.matches(job.output)
+            && entry.tool_version == self.version(job.tool)
+    }
+
+    /// Remember `job` after its output was produced.
+    pub fn record(&self, job: &Job) {
+        let inputs = job.inputs.iter()
+            .map(|x| Fingerprint::of(x).map(|p| (x.to_string_lossy().into_owned(), p)))
+            .collect::<std::io::Result<Vec<_>>>();
+        let entry = inputs.and_then(|inputs| Fingerprint::of(job.output).map(|output| Entry {
+            command: job.command.clone(),
+            settings: job.settings.clone(),
+            tool_version: self.version(job.tool),
+            inputs,
+            output,
+        }));
+        match entry {
+            Ok(entry) => {
+                self.entries.lock().unwrap().insert(job.output.to_string_lossy().into_owned(), entry);
+            }
+            Err(e) => warn!("cannot record {} in cache: {}", job.output.display(), e)
+        }
+    }
+}
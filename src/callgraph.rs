@@ -0,0 +1,167 @@
+//! Parser for the call graph printed by `opt`.
+//!
+//! The output is a sequence of nodes, each a header followed by one line per call site:
+//!
+//! ```text
+//! Call graph node <<null function>><<0x55d0c8a0a5a0>>  #uses=0
+//!   CS<None> calls function 'main'
+//!
+//! Call graph node for function: 'main'<<0x55d0c8a0a640>>  #uses=1
+//!   CS<0x55d0c8a0b9d8> calls function 'foo(int, char const*)'
+//!   CS<0x55d0c8a0ba88> calls external node
+//! ```
+//!
+//! Function names are taken verbatim between the quotes and may contain spaces and quotes
+//! themselves, as demangled C++ names do.
+
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callee {
+    Function(String),
+    /// A call through a pointer or into code outside the module.
+    External,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// The call instruction, or its address in older `opt`; `None` for the edges `opt` adds
+    /// itself, such as from a declaration to the external node.
+    pub site: Option<String>,
+    pub callee: Callee,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    /// `None` for the null function node standing for callers outside the module.
+    pub function: Option<String>,
+    pub address: Option<usize>,
+    pub uses: usize,
+    pub calls: Vec<Call>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A call site before the first node header.
+    CallOutsideNode,
+    /// A line that is neither a node header nor a call site.
+    UnexpectedLine(String),
+    /// A function name without its closing quote.
+    UnterminatedName,
+    BadAddress(String),
+    MissingUses,
+    BadUses(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number.
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ErrorKind::CallOutsideNode => write!(f, "call site outside any call graph node"),
+            ErrorKind::UnexpectedLine(line) => write!(f, "unexpected line: {}", line),
+            ErrorKind::UnterminatedName => write!(f, "unterminated function name"),
+            ErrorKind::BadAddress(x) => write!(f, "invalid node address {}", x),
+            ErrorKind::MissingUses => write!(f, "missing #uses in node header"),
+            ErrorKind::BadUses(x) => write!(f, "invalid #uses count {}", x),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+const NODE: &str = "Call graph node";
+const FUNCTION: &str = "for function: '";
+const NULL_FUNCTION: &str = "<<null function>>";
+const CALLS_FUNCTION: &str = " calls function '";
+const CALLS_EXTERNAL: &str = " calls external node";
+
+/// Split `rest` at its last `#uses=`, parsing the count.
+fn parse_uses(rest: &str) -> Result<(&str, usize), ErrorKind> {
+    let at = rest.rfind("#uses=").ok_or(ErrorKind::MissingUses)?;
+    let count = rest[at + "#uses=".len()..].trim();
+    count.parse()
+        .map(|uses| (rest[..at].trim_end(), uses))
+        .map_err(|_| ErrorKind::BadUses(String::from(count)))
+}
+
+/// Parse a trailing `<<0x...>>`, if any.
+fn parse_address(rest: &str) -> Result<(&str, Option<usize>), ErrorKind> {
+    if !rest.ends_with(">>") {
+        return Ok((rest, None));
+    }
+    let at = rest.rfind("<<").ok_or_else(|| ErrorKind::BadAddress(String::from(rest)))?;
+    let address = &rest[at + 2..rest.len() - 2];
+    usize::from_str_radix(address.trim_start_matches("0x"), 16)
+        .map(|x| (&rest[..at], Some(x)))
+        .map_err(|_| ErrorKind::BadAddress(String::from(address)))
+}
+
+fn parse_header(line: &str) -> Result<Node, ErrorKind> {
+    let rest = line[NODE.len()..].trim_start();
+    let (rest, uses) = parse_uses(rest)?;
+    if let Some(rest) = rest.strip_prefix(NULL_FUNCTION) {
+        let (_, address) = parse_address(rest)?;
+        return Ok(Node { function: None, address, uses, calls: Vec::new() });
+    }
+    let rest = rest.strip_prefix(FUNCTION)
+        .ok_or_else(|| ErrorKind::UnexpectedLine(String::from(line)))?;
+    let (name, address) = parse_address(rest)?;
+    let name = name.strip_suffix('\'').ok_or(ErrorKind::UnterminatedName)?;
+    Ok(Node { function: Some(String::from(name)), address, uses, calls: Vec::new() })
+}
+
+/// The text inside `CS<...>`, `None` for `CS<None>` and the `CS<0x0>` older `opt` prints instead.
+fn parse_site(site: &str) -> Option<String> {
+    let site = site.strip_prefix("CS<").and_then(|x| x.strip_suffix('>')).unwrap_or(site).trim();
+    match site {
+        "None" | "0x0" | "0" => None,
+        _ => Some(String::from(site)),
+    }
+}
+
+fn parse_call(line: &str) -> Result<Call, ErrorKind> {
+    if let Some(at) = line.find(CALLS_FUNCTION) {
+        return line[at + CALLS_FUNCTION.len()..].strip_suffix('\'')
+            .map(|x| Call { site: parse_site(&line[..at]), callee: Callee::Function(String::from(x)) })
+            .ok_or(ErrorKind::UnterminatedName);
+    }
+    if let Some(site) = line.strip_suffix(CALLS_EXTERNAL) {
+        return Ok(Call { site: parse_site(site), callee: Callee::External });
+    }
+    Err(ErrorKind::UnexpectedLine(String::from(line)))
+}
+
+/// Whether `line` is part of the printed call graph rather than a diagnostic `opt` wrote along
+/// with it to the same stream.
+pub fn is_graph_line(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with(NODE) || line.starts_with("CS<") || line.starts_with("Printing call graph")
+}
+
+/// Parse the whole output of the call graph printer.
+pub fn parse(text: &str) -> Result<Vec<Node>, ParseError> {
+    let mut nodes: Vec<Node> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let error = |kind| ParseError { line: i + 1, kind };
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with(NODE) {
+            nodes.push(parse_header(line).map_err(error)?);
+        } else if line.starts_with("CS<") {
+            let call = parse_call(line).map_err(error)?;
+            nodes.last_mut().ok_or_else(|| error(ErrorKind::CallOutsideNode))?.calls.push(call);
+        } else if !nodes.is_empty() {
+            return Err(error(ErrorKind::UnexpectedLine(String::from(line))));
+        }
+    }
+    Ok(nodes)
+}
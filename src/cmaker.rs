@@ -1,124 +1,59 @@
-use std::io::{Error, ErrorKind, Read, Write};
-use std::process::{Command, exit};
-use std::process::Stdio;
+use std::path::Path;
+use std::process::{Command, Stdio};
 
 use log::*;
 use serde::*;
 
-use crate::config::CONFIG;
-
-pub fn run_cmake() {
-    let compiling = std::fs::create_dir("rz_build")
-        .and_then(|_| std::env::set_current_dir("./rz_build"))
-        .and_then(|_| Command::new(&CONFIG.cmake_executable)
-            .env("CC", &CONFIG.original_cc_executable)
-            .env("CXX", &CONFIG.original_cxx_executable)
-            .arg(std::path::Path::new("../").join(&CONFIG.cmake_dir))
-            .args(&CONFIG.cmake_args).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn())
-        .and_then(|mut child| {
-            let res = child.wait();
-            let mut out = String::new();
-            let mut err = String::new();
-            child.stdout.as_mut().unwrap().read_to_string(&mut out)
-                .map(|_| ())
-                .unwrap_or_else(|x| warn!("cannot read cmake output {}", x));
-            child.stderr.as_mut().unwrap().read_to_string(&mut err)
-                .map(|_| ())
-                .unwrap_or_else(|x| warn!("cannot read cmake stderr {}", x));
-            trace!("cmake output: \n{}", out);
-            if !err.is_empty() { warn!("cmake stderr: \n{}", err); }
-            match res {
-                Ok(e) if e.success() => Ok(()),
-                Ok(f) => Err(Error::new(ErrorKind::Other, format!("cmake exit with failure {:?}", f))),
-                Err(e) => Err(e)
-            }
-        });
-    if compiling.is_err() {
-        error!("failed to run cmake command, {:#?}", compiling.err().unwrap());
-        exit(3);
-    }
+use crate::config::BuildBackend;
+use crate::error::{Error, Result, Tool};
+use crate::Pipeline;
+
+pub(crate) fn run_captured(tool: Tool, command: &mut Command) -> Result<String> {
+    let output = match command.stdout(Stdio::piped()).stderr(Stdio::piped()).output() {
+        Ok(output) => output,
+        Err(e) => return Error::tool(tool, None, Err(e)).map(|_| String::new())
+    };
+    let err = String::from_utf8_lossy(&output.stderr);
+    if !err.is_empty() { warn!("{} stderr: \n{}", tool.name(), err); }
+    Error::tool(tool, None, Ok(output.status))
+        .map(|_| String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
-pub fn run_remake() {
-    let cpu = num_cpus::get();
-    info!("start building with {} thread(s). ", cpu);
-    let making =
-        Command::new(&CONFIG.remake_executable).arg(format!("-j{}", cpu))
-            .arg("-x").arg("-Oline").stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
-            .and_then(|mut child| {
-                let mut out = String::new();
-                let mut err = String::new();
-                child.stdout.as_mut().unwrap().read_to_string(&mut out)
-                    .map(|_| ())
-                    .unwrap_or_else(|x| warn!("cannot read remake output {}", x));
-                child.stderr.as_mut().unwrap().read_to_string(&mut err)
-                    .map(|_| ())
-                    .unwrap_or_else(|x| warn!("cannot read remake stderr {}", x));
-                if !err.is_empty() { warn!("remake stderr: \n{}", err); }
-                match child.wait() {
-                    Ok(e) if e.success() => Ok(out),
-                    Ok(f) => Err(Error::new(ErrorKind::Other, format!("remake exit with failure {:?}", f))),
-                    Err(e) => Err(e)
-                }
-            });
-    match making {
-        Err(e) => {
-            error!("failed to run remake command, {:#?}", e);
-            exit(3);
-        }
-        Ok(res) => {
-            let storing = std::fs::File::create("remake.log")
-                .and_then(|mut f| f.write_all(res.as_bytes()));
-            if storing.is_err() {
-                error!("failed to store remake log, {:#?}", storing.err().unwrap());
-                exit(4);
-            }
-            info!("remake log saved at {:?}", std::fs::canonicalize("remake.log").unwrap())
-        }
-    }
+pub fn run_cmake(pipeline: &Pipeline) -> Result<()> {
+    let config = pipeline.config();
+    let build_dir = pipeline.build_dir();
+    std::fs::create_dir_all(&build_dir)
+        .map_err(Error::io(format!("failed to create {}", build_dir.display())))?;
+    run_captured(Tool::Cmake, Command::new(&config.cmake_executable)
+        .current_dir(&build_dir)
+        .env("CC", &config.original_cc_executable)
+        .env("CXX", &config.original_cxx_executable)
+        .arg(pipeline.work_dir().join(&config.cmake_dir))
+        .args(&config.cmake_args)
+        .args(config.compile_commands.as_ref().map(|_| "-DCMAKE_EXPORT_COMPILE_COMMANDS=ON"))
+        .args(match config.build_backend {
+            BuildBackend::Make => vec![],
+            BuildBackend::Ninja => vec![String::from("-G"), String::from("Ninja")],
+        })
+        .args(config.ninja_executable.as_ref()
+            .filter(|_| config.build_backend == BuildBackend::Ninja)
+            .map(|x| format!("-DCMAKE_MAKE_PROGRAM={}", x))))
+        .map(|out| trace!("cmake output: \n{}", out))
 }
 
-pub fn run_cmaker() {
-    let path_tuple = std::env::current_dir()
-        .and_then(|x|
-            x.to_str().ok_or(std::io::Error::new(ErrorKind::Other, "cannot initialize path"))
-                .map(String::from))
-        .and_then(|x|
-            std::fs::canonicalize("remake.log")
-                .map_err(|e| std::io::Error::new(ErrorKind::Other, e))
-                .and_then(|x| x.to_str().ok_or(std::io::Error::new(ErrorKind::Other, "cannot initialize path"))
-                    .map(String::from))
-                .map(move |y| (x.clone(), y, x + "/cmaker.log"))
-        );
-
-    let parsing =
-        path_tuple.and_then(|(work, log, output)|
-            Command::new(&CONFIG.cmaker_executable)
-                .args(&["-w", &work, "-o", &output, "-t", &log])
-                .stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
-                .and_then(|mut child| {
-                    let res = child.wait();
-                    let mut out = String::new();
-                    let mut err = String::new();
-                    child.stdout.as_mut().unwrap().read_to_string(&mut out)
-                        .map(|_| ())
-                        .unwrap_or_else(|x| warn!("cannot read cmaker output {}", x));
-                    child.stderr.as_mut().unwrap().read_to_string(&mut err)
-                        .map(|_| ())
-                        .unwrap_or_else(|x| warn!("cannot read cmaker output {}", x));
-                    if !err.is_empty() { warn!("cmaker stderr: \n{}", err); }
-                    match res {
-                        Ok(e) if e.success() => Ok(out),
-                        Ok(f) => Err(Error::new(ErrorKind::Other, format!("cmaker exit with failure {:?}", f))),
-                        Err(e) => Err(e)
-                    }
-                }));
-    if parsing.is_err() {
-        error!("failed to run cmaker command, {:#?}", parsing.err().unwrap());
-        exit(3);
-    } else {
-        info!("cmaker log saved at {:?}", std::fs::canonicalize("cmaker.log").unwrap())
-    }
+pub fn run_remake(pipeline: &Pipeline) -> Result<()> {
+    let cpu = pipeline.jobs();
+    info!("start building with {} thread(s). ", cpu);
+    let build_dir = pipeline.build_dir();
+    let res = run_captured(Tool::Remake, Command::new(&pipeline.config().remake_executable)
+        .current_dir(&build_dir)
+        .arg(format!("-j{}", cpu))
+        .arg("-x").arg("-Oline"))?;
+    let log = build_dir.join("remake.log");
+    std::fs::write(&log, res.as_bytes())
+        .map_err(Error::io("failed to store remake log"))?;
+    info!("remake log saved at {:?}", log);
+    Ok(())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -144,7 +79,9 @@ pub struct Object {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Symbol {
-    name: String
+    pub name: String,
+    #[serde(default)]
+    pub weak: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -152,22 +89,95 @@ pub struct Target {
     pub name: String,
     pub abs_path: String,
     pub dependencies: Vec<String>,
-    pub target_type: u8,
+    #[serde(rename = "target_type")]
+    pub kind: TargetKind,
+}
+
+/// What a link script produces.
+///
+/// Serialized by name; the numeric codes of older cmaker logs are still read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetKind {
+    Executable,
+    SharedLibrary,
+    StaticLibrary,
+    ObjectLibrary,
+    ModuleLibrary,
+}
+
+impl TargetKind {
+    pub const ALL: [TargetKind; 5] = [TargetKind::Executable, TargetKind::SharedLibrary,
+        TargetKind::StaticLibrary, TargetKind::ObjectLibrary, TargetKind::ModuleLibrary];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TargetKind::Executable => "executable",
+            TargetKind::SharedLibrary => "shared-library",
+            TargetKind::StaticLibrary => "static-library",
+            TargetKind::ObjectLibrary => "object-library",
+            TargetKind::ModuleLibrary => "module-library",
+        }
+    }
+
+    /// Whether linking the kind produces a loadable module rather than a bundle of objects.
+    pub fn is_linked(self) -> bool {
+        match self {
+            TargetKind::Executable | TargetKind::SharedLibrary | TargetKind::ModuleLibrary => true,
+            TargetKind::StaticLibrary | TargetKind::ObjectLibrary => false,
+        }
+    }
 }
 
+impl<'de> Deserialize<'de> for TargetKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = TargetKind;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a target kind name or code")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<TargetKind, E> {
+                TargetKind::ALL.get(v as usize).copied()
+                    .ok_or_else(|| E::custom(format!("unknown target type {}", v)))
+            }
 
-pub fn get_collection(build_dir: &str) -> Collection {
-    std::env::set_current_dir(build_dir).unwrap_or_else(|e|
-        {
-            error!("failed to change dir to ./rz_build, remove .progress if you need: {}", e);
-            exit(6);
+            fn visit_i64<E: de::Error>(self, v: i64) -> std::result::Result<TargetKind, E> {
+                self.visit_u64(v as u64)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<TargetKind, E> {
+                TargetKind::ALL.iter().copied().find(|x| x.name() == v)
+                    .ok_or_else(|| E::custom(format!("unknown target kind {}", v)))
+            }
         }
-    );
-    std::fs::read_to_string("cmaker.log")
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+
+pub fn get_collection(pipeline: &Pipeline) -> Result<Collection> {
+    read_collection(pipeline.build_dir().join("cmaker.log"))
+}
+
+pub fn store_collection<P: AsRef<Path>>(collection: &Collection, path: P) -> Result<()> {
+    let path = path.as_ref();
+    serde_json::to_string_pretty(collection)
+        .map_err(|e| Error::Parse(format!("failed to serialize collection: {}", e)))
+        .and_then(|x| std::fs::write(path, x)
+            .map_err(Error::io(format!("failed to store {}", path.display()))))?;
+    info!("collection saved at {:?}", path);
+    Ok(())
+}
+
+pub fn read_collection<P: AsRef<Path>>(path: P) -> Result<Collection> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path)
+        .map_err(Error::io(format!("failed to read {}", path.display())))
         .and_then(|mut x| simd_json::serde::from_str(&mut x)
-            .map_err(|x| std::io::Error::new(ErrorKind::Other, x)))
-        .unwrap_or_else(|x| {
-            error!("failed to read cmaker.log: {}", x);
-            exit(6);
-        })
+            .map_err(|e| Error::Parse(format!("failed to parse {}: {}", path.display(), e))))
 }
@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use serde::*;
+
+use crate::error::{Error, Result};
+use crate::shell;
+
+/// Options whose value is the next argument when not attached.
+const OPTIONS_WITH_VALUE: &[&str] = &[
+    "-D", "-U", "-I", "-include", "-imacros", "-isystem", "-idirafter", "-iquote", "-isysroot",
+    "-iprefix", "-iwithprefix", "-iwithprefixbefore", "-x", "-MF", "-MT", "-MQ", "-Xclang",
+    "-Xpreprocessor", "-Xassembler", "-Xlinker", "-target", "-arch", "--sysroot", "-L", "-l",
+];
+
+/// One compiler invocation taken from the build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompileCommand {
+    /// Directory the compiler runs in, `None` if the command did not change it.
+    pub cwd: Option<PathBuf>,
+    pub compiler: String,
+    /// Arguments other than the compiler and the `-o` output.
+    pub args: Vec<String>,
+    pub output: String,
+    /// Positional arguments, i.e. the files being compiled.
+    pub inputs: Vec<String>,
+}
+
+impl CompileCommand {
+    /// Parse a shell command line such as `cd dir && /usr/bin/c++ -c a.cpp -o a.o`,
+    /// taking the first simple command run by one of `compilers`.
+    pub fn parse(line: &str, compilers: &[&str]) -> Result<CompileCommand> {
+        let mut cwd: Option<PathBuf> = None;
+        for words in shell::commands(line)? {
+            if words[0] == "cd" {
+                let dir = PathBuf::from(words.get(1).map(String::as_str).unwrap_or("/"));
+                cwd = Some(match cwd {
+                    Some(cwd) => cwd.join(dir),
+                    None => dir,
+                });
+            } else if compilers.contains(&words[0].as_str()) {
+                return CompileCommand::from_words(cwd, words);
+            }
+        }
+        Err(Error::Parse(format!("no compiler invocation in compile command: {}", line)))
+    }
+
+    /// Build a command from `words`, the compiler followed by its arguments.
+    pub fn from_words(cwd: Option<PathBuf>, words: Vec<String>) -> Result<CompileCommand> {
+        let mut words = words.into_iter();
+        let compiler = words.next()
+            .ok_or_else(|| Error::Parse(String::from("empty compile command")))?;
+        let base = cwd.clone().unwrap_or_else(|| PathBuf::from("."));
+        let mut args = Vec::new();
+        expand_response_files(&base, words, &mut args, 0)?;
+
+        let mut rest = Vec::new();
+        let mut output = None;
+        let mut inputs = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "-o" {
+                output = args.next();
+            } else if arg.starts_with("-o") && !arg.starts_with("-objc") {
+                output = Some(String::from(&arg[2..]));
+            } else if OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+                rest.push(arg);
+                rest.extend(args.next());
+            } else {
+                if !arg.starts_with('-') {
+                    inputs.push(arg.clone());
+                }
+                rest.push(arg);
+            }
+        }
+        let output = output.ok_or_else(|| Error::Parse(
+            format!("no output object in compile command: {} {}", compiler, rest.join(" "))))?;
+        Ok(CompileCommand {
+            cwd,
+            compiler,
+            args: rest,
+            output,
+            inputs,
+        })
+    }
+
+    /// The arguments to emit bitcode to `output` with `compiler` instead, writing the headers
+    /// it includes to the depfile `<output>.d` rather than wherever the build wanted them.
+    pub fn to_llvm(&self, compiler: &str, output: &str) -> Vec<String> {
+        let mut command = vec![String::from(compiler), String::from("-emit-llvm")];
+        let mut args = self.args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-MD" | "-MMD" | "-MP" => (),
+                "-MF" | "-MT" | "-MQ" => {
+                    args.next();
+                }
+                x if x.starts_with("-MF") || x.starts_with("-MT") || x.starts_with("-MQ") => (),
+                _ => command.push(arg.clone()),
+            }
+        }
+        command.push(String::from("-MD"));
+        command.push(String::from("-MF"));
+        command.push(format!("{}.d", output));
+        command.push(String::from("-o"));
+        command.push(String::from(output));
+        command
+    }
+
+    /// The output resolved against `cwd`, which is how collections name objects.
+    pub fn object(&self) -> String {
+        match &self.cwd {
+            Some(cwd) => cwd.join(&self.output).to_string_lossy().into_owned(),
+            None => self.output.clone(),
+        }
+    }
+
+    /// Inputs as paths, resolved against `cwd` or else `default`.
+    pub fn input_paths(&self, default: &Path) -> Vec<PathBuf> {
+        let cwd = self.cwd.as_deref().unwrap_or(default);
+        self.inputs.iter().map(|x| cwd.join(x)).collect()
+    }
+}
+
+fn expand_response_files<I>(cwd: &Path, words: I, args: &mut Vec<String>, depth: usize) -> Result<()>
+    where I: Iterator<Item=String> {
+    for word in words {
+        if !word.starts_with('@') || word.len() == 1 {
+            args.push(word);
+            continue;
+        }
+        if depth > 16 {
+            return Err(Error::Parse(format!("response files nested too deeply at {}", word)));
+        }
+        let path = cwd.join(&word[1..]);
+        let content = std::fs::read_to_string(&path)
+            .map_err(Error::io(format!("failed to read response file {}", path.display())))?;
+        expand_response_files(cwd, shell::words(&content)?.into_iter(), args, depth + 1)?;
+    }
+    Ok(())
+}
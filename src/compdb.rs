@@ -0,0 +1,79 @@
+//! Compile commands from a JSON compilation database (`compile_commands.json`).
+
+use std::path::Path;
+
+use log::*;
+use serde::*;
+
+use crate::error::{Error, Result};
+use crate::shell;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub directory: String,
+    pub file: String,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<Vec<String>>,
+    #[serde(default)]
+    pub output: Option<String>,
+}
+
+impl Entry {
+    pub fn words(&self) -> Result<Vec<String>> {
+        match (&self.arguments, &self.command) {
+            (Some(args), _) => Ok(args.clone()),
+            (None, Some(command)) => shell::words(command),
+            (None, None) => Err(Error::Parse(format!("no command for {} in compilation database", self.file)))
+        }
+    }
+
+    /// Whether the entry compiles a source with one of `compilers` rather than running another tool.
+    pub fn is_compiled_by(&self, compilers: &[&str]) -> Result<bool> {
+        let words = self.words()?;
+        Ok(words.first().map(|x| compilers.contains(&x.as_str())).unwrap_or(false)
+            && words.iter().any(|x| x == "-c"))
+    }
+
+    /// The entry as a line of the compile list, `cd <directory> && <command> -o <output>`.
+    pub fn to_compile(&self) -> Result<String> {
+        let mut words = self.words()?;
+        if !words.iter().any(|x| x.starts_with("-o") && !x.starts_with("-objc")) {
+            match &self.output {
+                Some(output) => {
+                    words.push(String::from("-o"));
+                    words.push(output.clone());
+                }
+                None => return Err(Error::Parse(
+                    format!("no output for {} in compilation database", self.file)))
+            }
+        }
+        let command = words.iter().map(|x| shell::quote(x)).collect::<Vec<_>>().join(" ");
+        Ok(format!("cd {} && {}", shell::quote(&self.directory), command))
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Entry>> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path)
+        .map_err(Error::io(format!("failed to read {}", path.display())))
+        .and_then(|mut x| simd_json::serde::from_str(&mut x)
+            .map_err(|e| Error::Parse(format!("failed to parse {}: {}", path.display(), e))))
+}
+
+/// Read the compile list from the compilation database at `path`, skipping entries not run by
+/// one of `compilers`.
+pub fn compile_list<P: AsRef<Path>>(path: P, compilers: &[&str]) -> Result<Vec<String>> {
+    let entries = load(&path)?;
+    info!("loaded {} compile command(s) from {}", entries.len(), path.as_ref().display());
+    let mut compile = Vec::new();
+    for entry in &entries {
+        if entry.is_compiled_by(compilers)? {
+            compile.push(entry.to_compile()?);
+        } else {
+            warn!("skipping {}, not compiled by {}", entry.file, compilers.join(" or "));
+        }
+    }
+    Ok(compile)
+}
@@ -1,96 +1,165 @@
-use std::io::ErrorKind;
-use std::process::exit;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
 
+use hashbrown::HashSet;
 use log::*;
 use percent_encoding::percent_encode;
 use rayon::prelude::*;
 
+use crate::cache::{Cache, Job};
 use crate::cmaker::Collection;
-use crate::config::*;
+use crate::command::CompileCommand;
+use crate::config::CompilePolicy;
+use crate::error::{Error, Result, Tool};
+use crate::scheduler::{run, Schedule, Status};
+use crate::shell;
+use crate::Pipeline;
 
-pub fn compile_to_llvm(collection: &Collection) {
-    let commands = &collection.compile;
-    let a = crate::config::PWD.clone() + "/rz_build/objects";
-    let count = Arc::new(AtomicUsize::new(0));
-    let commands = commands.par_iter().map(|x| {
-        let count = count.clone();
-        let mut state = 0;
-        let mut real = String::new();
-        let mut object = None;
-        let mut relative = None;
-        for i in x.split_ascii_whitespace() {
-            if state == 10 {
-                relative.replace(String::from(i));
-                state = 20;
-                continue;
-            }
-            if i == "cd" {
-                state = 10;
-                continue;
-            }
-            if i == "&&" {
-                state = 0;
-                continue;
-            }
-            if state == 1 {
-                object.replace(i);
-                state = 2;
+#[derive(Debug, Default)]
+pub struct CompileReport {
+    pub compiled: usize,
+    /// Objects, as named in the collection, that failed to compile or, after a fail-fast stop,
+    /// were never attempted.
+    pub failed: Vec<String>,
+    pub error: Option<Error>,
+}
+
+impl CompileReport {
+    /// Whether the stage may be considered done under `policy`.
+    pub fn into_result(self, policy: CompilePolicy) -> Result<()> {
+        match (policy, self.error) {
+            (CompilePolicy::FailFast, Some(e)) => Err(e),
+            (CompilePolicy::KeepGoing, Some(_)) => Err(Error::Tool {
+                tool: Tool::Compiler,
+                target: self.failed.first().cloned(),
+                message: format!("{} object(s) failed to compile", self.failed.len()),
+            }),
+            (_, Some(_)) => {
+                warn!("ignoring {} object(s) that failed to compile", self.failed.len());
+                Ok(())
             }
-            if i == "-o" {
-                state = 1;
+            (_, None) => Ok(())
+        }
+    }
+}
+
+/// The prerequisites listed in a Makefile depfile such as the compiler writes with `-MD`.
+pub fn depfile_inputs(text: &str) -> Vec<String> {
+    let text = text.replace("\\\r\n", " ").replace("\\\n", " ");
+    let mut inputs = Vec::new();
+    for rule in text.lines() {
+        let colon = rule.char_indices()
+            .find(|(i, c)| *c == ':' && (i + 1 == rule.len() || rule[i + 1..].starts_with(char::is_whitespace)));
+        let mut chars = match colon {
+            Some((i, _)) => rule[i + 1..].chars().peekable(),
+            None => continue
+        };
+        let mut word = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&' ') => word.push(chars.next().unwrap()),
+                '$' if chars.peek() == Some(&'$') => word.push(chars.next().unwrap()),
+                c if c.is_whitespace() => if !word.is_empty() {
+                    inputs.push(std::mem::take(&mut word));
+                },
+                c => word.push(c),
             }
-            real += " ";
-            if i == CONFIG.original_cxx_executable {
-                real += &CONFIG.targeted_cxx_executable;
-                real += " -emit-llvm ";
-            } else if i == CONFIG.original_cc_executable {
-                real += &CONFIG.targeted_cc_executable;
-                real += " -emit-llvm ";
-            } else if state == 2 {
-                real += &a;
-                real += "/";
-                real += percent_encode(i.as_bytes(), crate::FRAGMENT).to_string().as_str();
-                state = 0;
-            } else {
-                real += i;
+        }
+        if !word.is_empty() {
+            inputs.push(word);
+        }
+    }
+    inputs
+}
+
+/// The files compiling `x` in `cwd` reads: its sources, and the headers they included when it was
+/// last compiled, from `depfile`.
+fn inputs(x: &CompileCommand, cwd: &Path, depfile: &Path) -> Vec<PathBuf> {
+    let mut inputs = x.input_paths(cwd);
+    if let Ok(text) = std::fs::read_to_string(depfile) {
+        let mut seen = inputs.iter().cloned().collect::<HashSet<_>>();
+        for dep in depfile_inputs(&text) {
+            let dep = cwd.join(dep);
+            if seen.insert(dep.clone()) {
+                inputs.push(dep);
             }
         }
-        trace!("[{}/{}] compiling {}: \n{}", count.fetch_add(1, Ordering::SeqCst), collection.compile.len(), object.as_ref().unwrap(), real);
-        (object.map(|x| percent_encode(x.as_bytes(), crate::FRAGMENT).to_string()).unwrap(), real, relative)
-    });
+    }
+    inputs
+}
 
-    if std::fs::metadata(&a).is_err() {
-        std::fs::create_dir(&a).unwrap_or_else(|x| {
-            error!("failed to create object dir {:?}: {}", a, x);
-            exit(10);
-        })
+/// Compile every object of `collection`, or only those named in `only`.
+pub fn compile_to_llvm(pipeline: &Pipeline, collection: &Collection, only: Option<&HashSet<String>>)
+                       -> Result<CompileReport> {
+    let config = pipeline.config();
+    let commands = &collection.compile;
+    let object_dir = pipeline.object_dir();
+    let a = object_dir.to_string_lossy().into_owned();
+    let compilers = [config.original_cxx_executable.as_str(), config.original_cc_executable.as_str()];
+    let commands = commands.par_iter()
+        .map(|x| CompileCommand::parse(x, &compilers))
+        .collect::<Result<Vec<_>>>()?;
+    let commands = match only {
+        Some(only) => commands.into_iter().filter(|x| only.contains(&x.object())).collect(),
+        None => commands
+    };
+
+    if std::fs::metadata(&object_dir).is_err() {
+        std::fs::create_dir(&object_dir)
+            .map_err(Error::io(format!("failed to create object dir {:?}", a)))?;
     }
-    std::env::set_current_dir(&a).unwrap_or_else(|e|
-        {
-            error!("cannot change dir: {}", e);
-            exit(20);
-        }
-    );
-    commands.for_each(|x : (String, String, Option<String>)| {
-        if std::fs::metadata(&x.0).is_ok() {
-            info!("found {}, using cached", x.0);
+    let cache = Cache::load(pipeline.cache_path())?;
+    let outcome = run("compiling", pipeline.jobs(), &Schedule::new(commands.len()),
+                      config.compile_policy != CompilePolicy::FailFast, |i| {
+        let x = &commands[i];
+        let object = x.object();
+        let encoded = percent_encode(object.as_bytes(), crate::FRAGMENT).to_string();
+        let output = object_dir.join(&encoded);
+        let cwd = x.cwd.clone().unwrap_or_else(|| object_dir.clone());
+        let compiler = if x.compiler == config.original_cxx_executable {
+            &config.targeted_cxx_executable
         } else {
-            let mut commands = x.1.split_ascii_whitespace().map(|x|x.replace("\\\"", "\""));
-            std::process::Command::new(commands.next().unwrap())
-                .args(commands)
-                .current_dir(x.2.as_ref().unwrap_or(&String::from(".")))
-                .spawn()
-                .and_then(|mut x| match x.wait() {
-                    Ok(e) if e.success() => Ok(()),
-                    Ok(e) => Err(std::io::Error::new(ErrorKind::Other, format!("failure with {:?}", e))),
-                    Err(e) => Err(e)
-                }
-                )
-                .unwrap_or_else(|e| {
-                    error!("cannot compile {}: {:?}", x.0, e)
-                });
+            &config.targeted_cc_executable
+        };
+        let args = x.to_llvm(compiler, &output.to_string_lossy());
+        let depfile = PathBuf::from(format!("{}.d", output.display()));
+        let job = Job {
+            output: &output,
+            tool: compiler,
+            inputs: inputs(x, &cwd, &depfile),
+            command: args.clone(),
+            settings: Vec::new(),
+        };
+        if cache.is_fresh(&job) {
+            info!("found {}, using cached", encoded);
+            return Ok(());
         }
+        trace!("compiling {}: \n{}", encoded, args.iter().map(|x| shell::quote(x)).collect::<Vec<_>>().join(" "));
+        let status = std::process::Command::new(&args[0])
+            .args(&args[1..])
+            .current_dir(&cwd)
+            .spawn()
+            .and_then(|mut x| x.wait());
+        if let Ok(true) = status.as_ref().map(|x| x.success()) {
+            // the headers may have changed along with the sources
+            cache.record(&Job { inputs: inputs(x, &cwd, &depfile), ..job });
+        }
+        Error::tool(Tool::Compiler, Some(&object), status)
+            .map_err(|e| {
+                error!("cannot compile {}: {}", object, e);
+                e
+            })
     });
-}
\ No newline at end of file
+    cache.store()?;
+    if let Some(e) = outcome.fatal {
+        return Err(e);
+    }
+    let failed = outcome.errors.iter().map(|x| x.0)
+        .chain((0..commands.len()).filter(|x| outcome.status[*x] == Status::Pending))
+        .map(|x| commands[x].object())
+        .collect::<Vec<_>>();
+    Ok(CompileReport {
+        compiled: outcome.status.iter().filter(|x| **x == Status::Succeeded).count(),
+        failed,
+        error: outcome.errors.into_iter().next().map(|x| x.1),
+    })
+}
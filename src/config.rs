@@ -1,60 +1,153 @@
 use std::env::var;
-use std::io::Read;
+use std::path::Path;
 
-use log::{error, info};
+use log::info;
 use serde::*;
-use simd_json::serde as S;
+
+use crate::cmaker::TargetKind;
+use crate::error::{Error, Result};
+use crate::export::Format;
 
 #[derive(Deserialize, Debug)]
 pub struct Configuration {
+    #[serde(default)]
     pub callpass_library_path: String,
     pub original_cxx_executable: String,
     pub original_cc_executable: String,
     pub targeted_cxx_executable: String,
     pub targeted_cc_executable: String,
     pub llvm_link_executable: String,
-    pub cmaker_executable: String,
     pub cmake_executable: String,
     pub remake_executable: String,
     pub llvm_opt_executable: String,
     pub cmake_args: Vec<String>,
-    pub cmake_dir: String
-}
-
-pub(crate) fn parse_config() -> Configuration {
-    let path = var("RZ_CONFIG").unwrap_or(String::from("./config.json"));
-    info!("loading config from: {}", path);
-    let config = std::fs::File::open(path)
-        .map_err(|x| error!("failed to open config file: {}", x))
-        .and_then(|mut x| {
-            let mut buf = String::new();
-            x.read_to_string(&mut buf).map(|_| buf)
-                .map_err(|x| error!("failed to read config file: {}", x))
-        })
-        .and_then(|mut x| S::from_str(x.as_mut_str())
-            .map_err(|x| error!("failed to parse config file: {}", x)));
-    match config {
-        Ok(x) => x,
-        Err(_) => {
-            std::process::exit(1)
+    pub cmake_dir: String,
+    #[serde(default)]
+    pub jobs: Option<usize>,
+    #[serde(default)]
+    pub strict_dependencies: bool,
+    #[serde(default)]
+    pub compile_policy: CompilePolicy,
+    #[serde(default)]
+    pub compile_commands: Option<String>,
+    #[serde(default)]
+    pub link_collection: Option<String>,
+    #[serde(default)]
+    pub build_backend: BuildBackend,
+    #[serde(default)]
+    pub ninja_executable: Option<String>,
+    #[serde(default)]
+    pub llvm_nm_executable: Option<String>,
+    #[serde(default)]
+    pub strict_symbols: bool,
+    #[serde(default = "default_graph_kinds")]
+    pub graph_kinds: Vec<TargetKind>,
+    #[serde(default)]
+    pub export_formats: Vec<Format>,
+    #[serde(default)]
+    pub graph_backend: GraphBackend,
+    #[serde(default)]
+    pub demangle: bool,
+    #[serde(default)]
+    pub llvm_dis_executable: Option<String>,
+    #[serde(default)]
+    pub include_targets: Vec<String>,
+    #[serde(default)]
+    pub exclude_targets: Vec<String>,
+}
+
+/// Which generator cmake configures the project for.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildBackend {
+    /// Makefiles, built and traced with remake.
+    #[default]
+    Make,
+    /// Ninja, queried with `ninja -t compdb` and `ninja -t commands`.
+    Ninja,
+}
+
+/// How `opt` is asked for the call graph of a linked module.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GraphBackend {
+    /// The `callpass_library_path` plugin, through the legacy `opt -load ... -dumpcalls`.
+    #[default]
+    Plugin,
+    /// Stock `opt -passes=print-callgraph`, no plugin needed.
+    PrintCallgraph,
+}
+
+impl std::str::FromStr for GraphBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "plugin" => Ok(GraphBackend::Plugin),
+            "print-callgraph" => Ok(GraphBackend::PrintCallgraph),
+            _ => Err(format!("unknown graph backend {}, expected one of: plugin, print-callgraph", s))
         }
     }
 }
 
-fn get_current_path() -> String {
-    std::env::current_dir()
-        .map_err(|e| error!("cannot get current dir {}", e))
-        .and_then(|x| x.to_str()
-            .ok_or_else(|| error!("cannot get current dir"))
-            .map(String::from))
-        .unwrap_or_else(|_| {
-            error!("cannot transform current dir");
-            std::process::exit(1)
-        })
+fn default_graph_kinds() -> Vec<TargetKind> {
+    vec![TargetKind::Executable, TargetKind::SharedLibrary]
+}
+
+/// What to do when an object fails to compile to bitcode.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompilePolicy {
+    /// Stop at the first failure.
+    #[default]
+    FailFast,
+    /// Compile everything else, then fail.
+    KeepGoing,
+    /// Log failures and carry on to linking.
+    Ignore,
 }
 
-lazy_static! {
-    pub static ref CONFIG : Configuration = parse_config();
-    pub static ref PWD : String = get_current_path();
+impl std::str::FromStr for CompilePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "fail-fast" => Ok(CompilePolicy::FailFast),
+            "keep-going" => Ok(CompilePolicy::KeepGoing),
+            "ignore" => Ok(CompilePolicy::Ignore),
+            _ => Err(format!("unknown compile policy {}, expected one of: fail-fast, keep-going, ignore", s))
+        }
+    }
 }
 
+impl Configuration {
+    pub fn ninja(&self) -> &str {
+        self.ninja_executable.as_deref().unwrap_or("ninja")
+    }
+
+    pub fn llvm_nm(&self) -> &str {
+        self.llvm_nm_executable.as_deref().unwrap_or("llvm-nm")
+    }
+
+    pub fn llvm_dis(&self) -> &str {
+        self.llvm_dis_executable.as_deref().unwrap_or("llvm-dis")
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Configuration> {
+        let path = path.as_ref();
+        info!("loading config from: {}", path.display());
+        std::fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("failed to read config file {}: {}", path.display(), e)))
+            // simd-json cannot read the enums below from their names
+            .and_then(|x| serde_json::from_str(&x)
+                .map_err(|e| Error::Config(format!("failed to parse config file {}: {}", path.display(), e))))
+    }
+
+    /// Load the config named by `RZ_CONFIG`, falling back to `config.json` in `work_dir`.
+    pub fn locate<P: AsRef<Path>>(work_dir: P) -> Result<Configuration> {
+        match var("RZ_CONFIG") {
+            Ok(path) => Configuration::from_file(path),
+            Err(_) => Configuration::from_file(work_dir.as_ref().join("config.json"))
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Where functions are defined, from the debug info of a linked module.
+//!
+//! The module is disassembled with `llvm-dis` and the `!dbg` attachment of every `define` is
+//! followed to its `DISubprogram` and `DIFile`:
+//!
+//! ```text
+//! define dso_local i32 @main() #0 !dbg !10 {
+//! !1 = !DIFile(filename: "main.c", directory: "/src")
+//! !10 = distinct !DISubprogram(name: "main", scope: !1, file: !1, line: 3, ...)
+//! ```
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use hashbrown::HashMap;
+use serde::*;
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+}
+
+/// Undo the `\XX` hex escapes of a quoted IR name or string.
+fn unescape(s: &str) -> String {
+    let mut out = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        match tail.get(..2).and_then(|x| u8::from_str_radix(std::str::from_utf8(x).ok()?, 16).ok()) {
+            Some(x) if b == b'\\' => {
+                out.push(x);
+                rest = &tail[2..];
+            }
+            _ => {
+                out.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// A quoted string at the start of `s`, with the rest after the closing quote.
+fn quoted(s: &str) -> Option<(String, &str)> {
+    let s = s.strip_prefix('"')?;
+    let end = s.find('"')?;
+    Some((unescape(&s[..end]), &s[end + 1..]))
+}
+
+/// The name in `@name` or `@"name"` at the start of `s`.
+fn global(s: &str) -> Option<String> {
+    let s = s.strip_prefix('@')?;
+    if s.starts_with('"') {
+        return quoted(s).map(|(x, _)| x);
+    }
+    let end = s.find(|c: char| !(c.is_ascii_alphanumeric() || "-$._".contains(c))).unwrap_or(s.len());
+    Some(String::from(&s[..end])).filter(|x| !x.is_empty())
+}
+
+/// The raw value of field `key` in a specialized metadata node such as `!DIFile(...)`.
+fn field<'a>(node: &'a str, key: &str) -> Option<&'a str> {
+    let body = &node[node.find('(')? + 1..node.rfind(')')?];
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut string = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            '"' => string = !string,
+            ',' if !string => {
+                fields.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(&body[start..]);
+    fields.into_iter()
+        .filter_map(|x| x.split_once(':'))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim())
+}
+
+/// Source locations of the functions defined in the textual IR `ir`, by IR name.
+pub fn parse(ir: &str) -> HashMap<String, Location> {
+    let mut functions = Vec::new();
+    let mut metadata = HashMap::new();
+    for line in ir.lines() {
+        if line.starts_with("define ") {
+            let body = line.rfind('{').map(|x| &line[..x]).unwrap_or(line);
+            let name = body.find(" @").and_then(|x| global(&body[x + 1..]));
+            let dbg = body.rfind("!dbg !").and_then(|x| body[x + "!dbg ".len()..].split_whitespace().next());
+            if let (Some(name), Some(dbg)) = (name, dbg) {
+                functions.push((name, dbg));
+            }
+        } else if line.starts_with('!') {
+            if let Some(at) = line.find(" = ") {
+                let value = line[at + 3..].trim_start_matches("distinct ");
+                metadata.insert(&line[..at], value);
+            }
+        }
+    }
+
+    let file = |id: &str| -> Option<String> {
+        let node = metadata.get(id)?;
+        let (name, _) = quoted(field(node, "filename")?)?;
+        let directory = field(node, "directory").and_then(quoted).map(|(x, _)| x).unwrap_or_default();
+        if name.starts_with('/') || directory.is_empty() {
+            Some(name)
+        } else {
+            Some(Path::new(&directory).join(name).to_string_lossy().into_owned())
+        }
+    };
+    functions.into_iter()
+        .filter_map(|(name, dbg)| {
+            let node = metadata.get(dbg)?;
+            let line = field(node, "line").and_then(|x| x.parse().ok())?;
+            let file = file(field(node, "file")?)?;
+            Some((name, Location { file, line }))
+        })
+        .collect()
+}
+
+/// Source locations of the functions `module` defines, through `llvm-dis`.
+pub fn locations(llvm_dis: &str, module: &str) -> Result<HashMap<String, Location>> {
+    let output = Command::new(llvm_dis)
+        .arg("-o")
+        .arg("-")
+        .arg(module)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(Error::io(format!("failed to run {} on {}", llvm_dis, module)))?;
+    if !output.status.success() {
+        return Err(Error::Parse(format!("{} cannot disassemble {}: {:?}", llvm_dis, module, output.status)));
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
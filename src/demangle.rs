@@ -0,0 +1,17 @@
+//! Readable names for mangled C++ (Itanium) and Rust symbols.
+
+use cpp_demangle::{DemangleOptions, Symbol};
+
+/// The demangled form of `name`, `None` if it is not a mangled name.
+///
+/// Rust's legacy mangling is valid Itanium as well, so Rust is tried first to drop the hash.
+pub fn demangle(name: &str) -> Option<String> {
+    if let Ok(x) = rustc_demangle::try_demangle(name) {
+        return Some(format!("{:#}", x));
+    }
+    if !name.starts_with("_Z") {
+        return None;
+    }
+    Symbol::new(name).ok()
+        .and_then(|x| x.demangle(&DemangleOptions::default()).ok())
+}
@@ -0,0 +1,162 @@
+//! Errors raised by the pipeline.
+//!
+//! Each variant maps to a stable process exit code so wrappers can tell failures apart:
+//!
+//! | code | failure                                   |
+//! |------|-------------------------------------------|
+//! | 1    | invalid or unreadable configuration       |
+//! | 2    | I/O failure (work dir, logs, progress)    |
+//! | 3    | malformed input (build log, progress)     |
+//! | 4    | inconsistent target dependency graph      |
+//! | 5    | query names an unknown function or target |
+//! | 10   | `cmake` failed                            |
+//! | 11   | `remake` failed                           |
+//! | 13   | `ninja` failed                            |
+//! | 20   | compiling to LLVM bitcode failed          |
+//! | 30   | `llvm-nm` failed                          |
+//! | 50   | `llvm-link` failed                        |
+//! | 60   | `opt` failed                              |
+
+use std::fmt::{Display, Formatter};
+use std::process::ExitStatus;
+
+use serde::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tool {
+    Cmake,
+    Remake,
+    Ninja,
+    Compiler,
+    LlvmNm,
+    LlvmLink,
+    Opt,
+}
+
+impl Tool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Cmake => "cmake",
+            Tool::Remake => "remake",
+            Tool::Ninja => "ninja",
+            Tool::Compiler => "compiler",
+            Tool::LlvmNm => "llvm-nm",
+            Tool::LlvmLink => "llvm-link",
+            Tool::Opt => "opt",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Config(String),
+    Io(String, std::io::Error),
+    Parse(String),
+    Graph(String),
+    /// A query naming a function or target the call graphs do not have.
+    Query(String),
+    Tool {
+        tool: Tool,
+        target: Option<String>,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Machine-readable description of a failure, written next to `.progress` by the binary.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Summary {
+    pub kind: String,
+    pub exit_code: i32,
+    pub tool: Option<Tool>,
+    pub target: Option<String>,
+    pub message: String,
+}
+
+impl Error {
+    pub(crate) fn io<S: Into<String>>(context: S) -> impl FnOnce(std::io::Error) -> Error {
+        let context = context.into();
+        move |e| Error::Io(context, e)
+    }
+
+    /// Build a tool failure from the result of waiting on it.
+    pub(crate) fn tool(tool: Tool, target: Option<&str>, status: std::io::Result<ExitStatus>) -> Result<()> {
+        let message = match status {
+            Ok(e) if e.success() => return Ok(()),
+            Ok(e) => format!("{} exit with failure {:?}", tool.name(), e),
+            Err(e) => format!("failed to run {}: {}", tool.name(), e),
+        };
+        Err(Error::Tool {
+            tool,
+            target: target.map(String::from),
+            message,
+        })
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Config(_) => "config",
+            Error::Io(..) => "io",
+            Error::Parse(_) => "parse",
+            Error::Graph(_) => "graph",
+            Error::Query(_) => "query",
+            Error::Tool { .. } => "tool",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_) => 1,
+            Error::Io(..) => 2,
+            Error::Parse(_) => 3,
+            Error::Graph(_) => 4,
+            Error::Query(_) => 5,
+            Error::Tool { tool: Tool::Cmake, .. } => 10,
+            Error::Tool { tool: Tool::Remake, .. } => 11,
+            Error::Tool { tool: Tool::Ninja, .. } => 13,
+            Error::Tool { tool: Tool::Compiler, .. } => 20,
+            Error::Tool { tool: Tool::LlvmNm, .. } => 30,
+            Error::Tool { tool: Tool::LlvmLink, .. } => 50,
+            Error::Tool { tool: Tool::Opt, .. } => 60,
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        let (tool, target) = match self {
+            Error::Tool { tool, target, .. } => (Some(*tool), target.clone()),
+            _ => (None, None)
+        };
+        Summary {
+            kind: String::from(self.kind()),
+            exit_code: self.exit_code(),
+            tool,
+            target,
+            message: self.to_string(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Config(msg) => write!(f, "config error: {}", msg),
+            Error::Io(context, e) => write!(f, "{}: {}", context, e),
+            Error::Parse(msg) => write!(f, "parse error: {}", msg),
+            Error::Graph(msg) => write!(f, "dependency graph error: {}", msg),
+            Error::Query(msg) => write!(f, "query error: {}", msg),
+            Error::Tool { target: Some(target), message, .. } => write!(f, "{} (target {})", message, target),
+            Error::Tool { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) => Some(e),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Call graphs in formats other tools read: Graphviz DOT, GraphML (yEd) and GEXF (Gephi).
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use hashbrown::HashMap;
+use log::*;
+use percent_encoding::percent_encode;
+use serde::*;
+
+use crate::cmaker::Collection;
+use crate::debuginfo::Location;
+use crate::error::{Error, Result};
+use crate::graph::{read_graph, GraphNode};
+use crate::project::ProjectGraph;
+use crate::Pipeline;
+
+pub struct Node<'a> {
+    pub name: &'a str,
+    pub target: Option<&'a str>,
+    pub uses: usize,
+    /// Whether the graph has the function's body rather than only a declaration or calls to it.
+    pub defined: bool,
+    pub demangled: Option<&'a str>,
+    pub location: Option<&'a Location>,
+}
+
+impl<'a> Node<'a> {
+    /// The name to show, demangled if it could be.
+    pub fn label(&self) -> &'a str {
+        self.demangled.unwrap_or(self.name)
+    }
+}
+
+/// A call graph as seen by the exporters, either of one target or of the whole project.
+#[derive(Default)]
+pub struct Graph<'a> {
+    pub nodes: Vec<Node<'a>>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl<'a> Graph<'a> {
+    /// The call graph of one target; callees without a node of their own get one.
+    pub fn from_nodes(list: &'a [GraphNode]) -> Self {
+        let mut graph = Graph::default();
+        let mut index = HashMap::new();
+        let mut node = |graph: &mut Graph<'a>, name: &'a str, x: Option<&'a GraphNode>| *index.entry(name).or_insert_with(|| {
+            graph.nodes.push(Node {
+                name,
+                target: None,
+                uses: x.map(|x| x.uses).unwrap_or(0),
+                defined: x.map(|x| x.defined).unwrap_or(false),
+                demangled: x.and_then(|x| x.demangled.as_deref()),
+                location: x.and_then(|x| x.location.as_ref()),
+            });
+            graph.nodes.len() - 1
+        });
+        for x in list {
+            node(&mut graph, &x.name, Some(x));
+        }
+        for x in list {
+            let from = node(&mut graph, &x.name, Some(x));
+            for callee in x.callees() {
+                let to = node(&mut graph, callee, None);
+                graph.edges.push((from, to));
+            }
+        }
+        graph
+    }
+}
+
+impl<'a> From<&'a ProjectGraph> for Graph<'a> {
+    fn from(project: &'a ProjectGraph) -> Self {
+        Graph {
+            nodes: project.nodes.iter()
+                .map(|x| Node {
+                    name: &x.name,
+                    target: x.target.as_deref(),
+                    uses: x.uses,
+                    defined: x.target.is_some(),
+                    demangled: x.demangled.as_deref(),
+                    location: x.location.as_ref(),
+                })
+                .collect(),
+            edges: project.nodes.iter().enumerate()
+                .flat_map(|(i, x)| x.calls.iter().map(move |j| (i, *j)))
+                .collect(),
+        }
+    }
+}
+
+/// Renders a call graph into one file format.
+pub trait Exporter {
+    /// File extension, without the dot.
+    fn extension(&self) -> &'static str;
+    fn export(&self, graph: &Graph) -> String;
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+pub struct Dot;
+
+impl Exporter for Dot {
+    fn extension(&self) -> &'static str {
+        "dot"
+    }
+
+    fn export(&self, graph: &Graph) -> String {
+        let mut dot = String::from("digraph calls {\n");
+        for (i, x) in graph.nodes.iter().enumerate() {
+            write!(dot, "    {} [label={:?}", i, x.label()).unwrap();
+            if let Some(target) = x.target {
+                write!(dot, ", target={:?}", target).unwrap();
+            }
+            if let Some(location) = x.location {
+                write!(dot, ", location=\"{}:{}\"", location.file.escape_debug(), location.line).unwrap();
+            }
+            dot += "];\n";
+        }
+        for (from, to) in &graph.edges {
+            writeln!(dot, "    {} -> {};", from, to).unwrap();
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+pub struct GraphMl;
+
+impl Exporter for GraphMl {
+    fn extension(&self) -> &'static str {
+        "graphml"
+    }
+
+    fn export(&self, graph: &Graph) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>\n",
+            "  <key id=\"target\" for=\"node\" attr.name=\"target\" attr.type=\"string\"/>\n",
+            "  <key id=\"uses\" for=\"node\" attr.name=\"uses\" attr.type=\"int\"/>\n",
+            "  <key id=\"demangled\" for=\"node\" attr.name=\"demangled\" attr.type=\"string\"/>\n",
+            "  <key id=\"file\" for=\"node\" attr.name=\"file\" attr.type=\"string\"/>\n",
+            "  <key id=\"line\" for=\"node\" attr.name=\"line\" attr.type=\"int\"/>\n",
+            "  <graph id=\"calls\" edgedefault=\"directed\">\n"));
+        for (i, x) in graph.nodes.iter().enumerate() {
+            write!(xml, "    <node id=\"n{}\"><data key=\"name\">{}</data>", i, escape_xml(x.name)).unwrap();
+            if let Some(target) = x.target {
+                write!(xml, "<data key=\"target\">{}</data>", escape_xml(target)).unwrap();
+            }
+            write!(xml, "<data key=\"uses\">{}</data>", x.uses).unwrap();
+            if let Some(demangled) = x.demangled {
+                write!(xml, "<data key=\"demangled\">{}</data>", escape_xml(demangled)).unwrap();
+            }
+            if let Some(location) = x.location {
+                write!(xml, "<data key=\"file\">{}</data><data key=\"line\">{}</data>",
+                       escape_xml(&location.file), location.line).unwrap();
+            }
+            xml += "</node>\n";
+        }
+        for (from, to) in &graph.edges {
+            writeln!(xml, "    <edge source=\"n{}\" target=\"n{}\"/>", from, to).unwrap();
+        }
+        xml += "  </graph>\n</graphml>\n";
+        xml
+    }
+}
+
+pub struct Gexf;
+
+impl Exporter for Gexf {
+    fn extension(&self) -> &'static str {
+        "gexf"
+    }
+
+    fn export(&self, graph: &Graph) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<gexf xmlns=\"http://www.gexf.net/1.2draft\" version=\"1.2\">\n",
+            "  <graph mode=\"static\" defaultedgetype=\"directed\">\n",
+            "    <attributes class=\"node\">\n",
+            "      <attribute id=\"target\" title=\"target\" type=\"string\"/>\n",
+            "      <attribute id=\"uses\" title=\"uses\" type=\"integer\"/>\n",
+            "      <attribute id=\"demangled\" title=\"demangled\" type=\"string\"/>\n",
+            "      <attribute id=\"file\" title=\"file\" type=\"string\"/>\n",
+            "      <attribute id=\"line\" title=\"line\" type=\"integer\"/>\n",
+            "    </attributes>\n",
+            "    <nodes>\n"));
+        for (i, x) in graph.nodes.iter().enumerate() {
+            write!(xml, "      <node id=\"{}\" label=\"{}\"><attvalues>", i, escape_xml(x.label())).unwrap();
+            if let Some(target) = x.target {
+                write!(xml, "<attvalue for=\"target\" value=\"{}\"/>", escape_xml(target)).unwrap();
+            }
+            write!(xml, "<attvalue for=\"uses\" value=\"{}\"/>", x.uses).unwrap();
+            if let Some(demangled) = x.demangled {
+                write!(xml, "<attvalue for=\"demangled\" value=\"{}\"/>", escape_xml(demangled)).unwrap();
+            }
+            if let Some(location) = x.location {
+                write!(xml, "<attvalue for=\"file\" value=\"{}\"/><attvalue for=\"line\" value=\"{}\"/>",
+                       escape_xml(&location.file), location.line).unwrap();
+            }
+            xml += "</attvalues></node>\n";
+        }
+        xml += "    </nodes>\n    <edges>\n";
+        for (i, (from, to)) in graph.edges.iter().enumerate() {
+            writeln!(xml, "      <edge id=\"{}\" source=\"{}\" target=\"{}\"/>", i, from, to).unwrap();
+        }
+        xml += "    </edges>\n  </graph>\n</gexf>\n";
+        xml
+    }
+}
+
+/// The built-in exporters, as named in `export_formats` and `--export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    Dot,
+    Graphml,
+    Gexf,
+}
+
+impl Format {
+    pub fn exporter(self) -> Box<dyn Exporter> {
+        match self {
+            Format::Dot => Box::new(Dot),
+            Format::Graphml => Box::new(GraphMl),
+            Format::Gexf => Box::new(Gexf),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "graphml" => Ok(Format::Graphml),
+            "gexf" => Ok(Format::Gexf),
+            _ => Err(format!("unknown export format {}, expected one of: dot, graphml, gexf", s))
+        }
+    }
+}
+
+/// Write `graph` once per exporter, to `base` followed by the exporter's extension.
+pub fn write_all(graph: &Graph, base: &Path, exporters: &[Box<dyn Exporter>]) -> Result<()> {
+    for exporter in exporters {
+        let output = PathBuf::from(format!("{}.{}", base.display(), exporter.extension()));
+        std::fs::write(&output, exporter.export(graph))
+            .map_err(Error::io(format!("failed to store {}", output.display())))?;
+    }
+    Ok(())
+}
+
+/// Export the call graph of every target in `collection` that has one, and the project graph.
+pub fn export(pipeline: &Pipeline, collection: &Collection, project: &ProjectGraph) -> Result<()> {
+    let formats = &pipeline.config().export_formats;
+    if formats.is_empty() {
+        return Ok(());
+    }
+    let exporters = formats.iter().map(|x| x.exporter()).collect::<Vec<_>>();
+    for script in &collection.scripts {
+        let path = pipeline.graph_dir()
+            .join(percent_encode(script.target.abs_path.as_bytes(), crate::FRAGMENT).to_string());
+        if std::fs::metadata(&path).is_ok() {
+            let nodes = read_graph(&path)?;
+            write_all(&Graph::from_nodes(&nodes), &path, &exporters)?;
+        }
+    }
+    write_all(&Graph::from(project), &pipeline.build_dir().join("project_graph"), &exporters)?;
+    info!("call graphs exported as {:?}", formats);
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! Restrict a collection to selected targets and what they depend on.
+
+use std::str::FromStr;
+
+use hashbrown::HashSet;
+use log::*;
+
+use crate::cmaker::{Collection, Target, TargetKind};
+use crate::command::CompileCommand;
+use crate::error::{Error, Result};
+use crate::targets::TargetGraph;
+
+/// `kind:<kind>`, or a glob matched against the target name and path.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Kind(TargetKind),
+    Glob(glob::Pattern),
+}
+
+impl Pattern {
+    pub fn matches(&self, target: &Target) -> bool {
+        match self {
+            Pattern::Kind(kind) => target.kind == *kind,
+            Pattern::Glob(glob) => glob.matches(&target.name) || glob.matches(&target.abs_path),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Some(kind) = s.strip_prefix("kind:") {
+            return TargetKind::ALL.iter().copied().find(|x| x.name() == kind)
+                .map(Pattern::Kind)
+                .ok_or_else(|| format!("unknown target kind {}, expected one of: {}", kind,
+                                       TargetKind::ALL.iter().map(|x| x.name()).collect::<Vec<_>>().join(", ")));
+        }
+        glob::Pattern::new(s)
+            .map(Pattern::Glob)
+            .map_err(|e| format!("invalid target pattern {}: {}", s, e))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TargetFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl TargetFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<TargetFilter> {
+        let parse = |x: &[String]| x.iter()
+            .map(|p| p.parse().map_err(Error::Config))
+            .collect::<Result<Vec<Pattern>>>();
+        Ok(TargetFilter {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether `target` is selected itself, not counting dependencies of selected targets.
+    pub fn selects(&self, target: &Target) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|x| x.matches(target)))
+            && !self.exclude.iter().any(|x| x.matches(target))
+    }
+
+    /// Keep the selected targets with their transitive dependencies, the objects they link
+    /// and the commands compiling those objects.
+    pub fn apply(&self, collection: Collection, compilers: &[&str]) -> Result<Collection> {
+        if self.is_empty() {
+            return Ok(collection);
+        }
+        let (keep, objects) = {
+            let graph = TargetGraph::new(&collection);
+            let mut keep = vec![false; graph.len()];
+            let mut stack = (0..graph.len())
+                .filter(|x| self.selects(&graph.script(*x).target))
+                .collect::<Vec<_>>();
+            if stack.is_empty() {
+                return Err(Error::Config(String::from("no target matches the target filters")));
+            }
+            while let Some(i) = stack.pop() {
+                if !keep[i] {
+                    keep[i] = true;
+                    stack.extend_from_slice(graph.dependencies(i));
+                }
+            }
+            let objects = (0..graph.len())
+                .filter(|x| keep[*x])
+                .flat_map(|x| graph.script(x).target.dependencies.iter())
+                .filter(|x| graph.is_object(x))
+                .cloned()
+                .collect::<HashSet<_>>();
+            (keep, objects)
+        };
+
+        let mut compile = Vec::new();
+        for line in collection.compile {
+            let command = CompileCommand::parse(&line, compilers)?;
+            if objects.contains(&command.object()) {
+                compile.push(line);
+            }
+        }
+        let mut keep = keep.into_iter();
+        let scripts = collection.scripts.into_iter()
+            .filter(|_| keep.next().unwrap())
+            .collect::<Vec<_>>();
+        let objects = collection.objects.into_iter()
+            .filter(|x| objects.contains(&x.abs_path))
+            .collect::<Vec<_>>();
+        info!("target filters select {} target(s), {} object(s) and {} compile command(s)",
+              scripts.len(), objects.len(), compile.len());
+        Ok(Collection {
+            objects,
+            scripts,
+            compile,
+        })
+    }
+}
@@ -1,133 +1,246 @@
-use std::io::{BufRead, ErrorKind};
-use std::process::{exit, Stdio};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 
+use hashbrown::{HashMap, HashSet};
 use log::*;
 use percent_encoding::percent_encode;
-use rayon::prelude::*;
 use serde::*;
+
+use crate::cache::{Cache, Job};
+use crate::callgraph::{self, Call, Callee};
 use crate::cmaker::Collection;
+use crate::config::{Configuration, GraphBackend};
+use crate::debuginfo::{self, Location};
+use crate::demangle::demangle;
+use crate::error::{Error, Result, Tool};
+use crate::scheduler::{run, Schedule};
+use crate::Pipeline;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CallKind {
+    /// A call to a function defined in the module.
+    Direct,
+    /// A call through a pointer.
+    Indirect,
+    /// A call to a function the module only declares.
+    External,
+    /// A call to an `llvm.*` intrinsic.
+    Intrinsic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallEdge {
+    /// `None` for indirect calls.
+    pub callee: Option<String>,
+    /// Number of call sites.
+    pub count: usize,
+    pub kind: CallKind,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GraphNode {
-    name: String,
-    uses: usize,
-    address: usize,
-    call_list: Vec<String>
+    pub name: String,
+    pub uses: usize,
+    pub address: usize,
+    pub calls: Vec<CallEdge>,
+    /// Whether the module has the function's body, rather than a declaration or an intrinsic.
+    #[serde(default)]
+    pub defined: bool,
+    /// Set when `demangle` is on and `name` is a mangled C++ or Rust name.
+    #[serde(default)]
+    pub demangled: Option<String>,
+    /// Where the function is defined, if the module has debug info for it.
+    #[serde(default)]
+    pub location: Option<Location>,
 }
 
-pub(crate) fn gen_graph(collection: &Collection) {
-    let a = crate::config::PWD.clone() + "/rz_build/graph";
+impl GraphNode {
+    /// Names of the called functions, whatever the kind of call.
+    pub fn callees(&self) -> impl Iterator<Item=&str> {
+        self.calls.iter().filter_map(|x| x.callee.as_deref())
+    }
+}
+
+pub fn gen_graph(pipeline: &Pipeline, collection: &Collection) -> Result<()> {
+    let config = pipeline.config();
+    if config.graph_backend == GraphBackend::Plugin && config.callpass_library_path.is_empty() {
+        return Err(Error::Config(String::from("callpass_library_path is required by the plugin graph backend")));
+    }
+    let a = pipeline.graph_dir().to_string_lossy().into_owned();
+    let objects = pipeline.object_dir().to_string_lossy().into_owned();
     if std::fs::metadata(&a).is_err() {
-        std::fs::create_dir(&a).unwrap_or_else(|x| {
-            error!("unable to create dir {}: {:#?}", a, x);
-            exit(100);
-        })
+        std::fs::create_dir(&a)
+            .map_err(Error::io(format!("unable to create dir {}", a)))?;
     }
-    collection.scripts.par_iter().filter(|x| x.target.target_type < 2).for_each(|x| {
+    let scripts = collection.scripts.iter()
+        .filter(|x| config.graph_kinds.contains(&x.target.kind))
+        .collect::<Vec<_>>();
+    let cache = Cache::load(pipeline.cache_path())?;
+    // every target is attempted, a failed one does not keep the others from their call graphs
+    let outcome = run("generating call graph", pipeline.jobs(), &Schedule::new(scripts.len()), true, |i| {
+        let x = scripts[i];
         let m = x.target.abs_path.as_str();
         let encoded = percent_encode(m.as_bytes(), crate::FRAGMENT).to_string();
-        let path = crate::config::PWD.clone() + "/rz_build/objects/" + encoded.as_str();
+        let path = objects.clone() + "/" + encoded.as_str();
         let output = a.clone() + "/" + encoded.as_str();
-        if std::fs::metadata(&output).is_err() {
-            std::process::Command::new(&crate::config::CONFIG.llvm_opt_executable)
-                .arg("-load")
-                .arg(&crate::config::CONFIG.callpass_library_path)
-                .arg("-dumpcalls")
-                .arg(&path)
-                .env("CALLGRAPH_STORE", &output)
-                .stderr(Stdio::piped())
-                .stdout(Stdio::null())
-                .spawn()
-                .and_then(|mut x| {
-                    let p = x.stderr.take().unwrap();
-                    let reader = std::io::BufReader::new(p);
-                    for i in reader.lines().map(|x| x.unwrap()) {
-                        warn!("message from {}:\n {}", &path, i);
-                    }
-                    match x.wait() {
-                        Ok(e) if e.success() => Ok(()),
-                        Ok(e) => {
-                            Err(std::io::Error::new(ErrorKind::Other, format!("failed with {:#?}", e)))
+        let (command, inputs) = match config.graph_backend {
+            GraphBackend::Plugin => (
+                vec![config.llvm_opt_executable.clone(), String::from("-load"),
+                     config.callpass_library_path.clone(), String::from("-dumpcalls"), path.clone()],
+                vec![PathBuf::from(&path), PathBuf::from(&config.callpass_library_path)],
+            ),
+            GraphBackend::PrintCallgraph => (
+                vec![config.llvm_opt_executable.clone(), String::from("-passes=print-callgraph"),
+                     String::from("-disable-output"), path.clone()],
+                vec![PathBuf::from(&path)],
+            ),
+        };
+        let mut opt = std::process::Command::new(&command[0]);
+        opt.args(&command[1..]).stdout(Stdio::null());
+        let job = Job {
+            output: Path::new(&output),
+            tool: &config.llvm_opt_executable,
+            command,
+            // the graph is annotated after opt ran
+            settings: vec![format!("demangle={}", config.demangle), format!("llvm-dis={}", config.llvm_dis())],
+            inputs,
+        };
+        if std::fs::metadata(&path).is_err() {
+            warn!("{} is not linked, skipping its call graph", m);
+            Ok(())
+        } else if !cache.is_fresh(&job) || read_graph(&output).is_err() {
+            let status = match config.graph_backend {
+                GraphBackend::Plugin => opt
+                    .env("CALLGRAPH_STORE", &output)
+                    .stderr(Stdio::piped())
+                    .spawn()
+                    .and_then(|mut x| {
+                        let reader = std::io::BufReader::new(x.stderr.take().unwrap());
+                        for i in reader.lines().map_while(std::io::Result::ok) {
+                            warn!("message from {}:\n {}", &path, i);
                         }
-                        Err(e) => Err(e)
-                    }
-                })
+                        x.wait()
+                    }),
+                // the printer writes to stderr, shared with whatever else opt has to say
+                GraphBackend::PrintCallgraph => std::fs::File::create(&output)
+                    .and_then(|mut file| {
+                        let mut x = opt.stderr(Stdio::piped()).spawn()?;
+                        let reader = std::io::BufReader::new(x.stderr.take().unwrap());
+                        let mut written = Ok(());
+                        for i in reader.lines().map_while(std::io::Result::ok) {
+                            if !callgraph::is_graph_line(&i) {
+                                warn!("message from {}:\n {}", &path, i);
+                            } else if written.is_ok() {
+                                written = writeln!(file, "{}", i);
+                            }
+                        }
+                        let status = x.wait();
+                        written.and(status)
+                    }),
+            };
+            Error::tool(Tool::Opt, Some(m), status)
                 .and_then(|_| process_graph(output.as_str()))
-                .unwrap_or_else(|e| {
-                error!("failed to gen callgraph for {}: {}", m, e);
-            });
+                .and_then(|_| annotate(config, output.as_str(), &path))
+                .map(|_| cache.record(&job))
+                .map_err(|e| {
+                    error!("failed to gen callgraph for {}: {}", m, e);
+                    e
+                })
         } else {
             info!("found {}, using cached", output);
+            Ok(())
         }
     });
+    cache.store()?;
+    if !outcome.errors.is_empty() {
+        error!("{} of {} call graph(s) failed", outcome.errors.len(), scripts.len());
+    }
+    outcome.into_result()
 }
 
-pub fn process_graph(path: &str) -> std::io::Result<()> {
-    let file = std::fs::read_to_string(path);
+pub fn read_graph<P: AsRef<Path>>(path: P) -> Result<Vec<GraphNode>> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path)
+        .map_err(Error::io(format!("failed to read {}", path.display())))
+        // simd-json cannot read `CallKind` from its name
+        .and_then(|x| serde_json::from_str(&x)
+            .map_err(|e| Error::Parse(format!("failed to parse call graph {}: {}", path.display(), e))))
+}
 
-    file.map(|f| {
-        let mut sections = Vec::new();
-        let mut buffer = Vec::new();
-        for l in f.lines() {
-            let l = l.trim();
-            if l.is_empty() {continue; }
-            if l.starts_with("Call graph node") && !buffer.is_empty() {
-                sections.push(buffer);
-                buffer = Vec::new();
-            }
-            buffer.push(String::from(l));
+/// Fold the call sites of one node into edges, one per callee and kind.
+fn edges(calls: Vec<Call>, declared: &HashSet<String>) -> Vec<CallEdge> {
+    let mut edges: Vec<CallEdge> = Vec::new();
+    for call in calls {
+        let (callee, kind) = match call.callee {
+            Callee::Function(name) if name.starts_with("llvm.") => (Some(name), CallKind::Intrinsic),
+            Callee::Function(name) if declared.contains(&name) => (Some(name), CallKind::External),
+            Callee::Function(name) => (Some(name), CallKind::Direct),
+            Callee::External if call.site.is_some() => (None, CallKind::Indirect),
+            // a declaration calling out of the module, already told by the kind of calls to it
+            Callee::External => continue,
+        };
+        match edges.iter_mut().find(|x| x.callee == callee && x.kind == kind) {
+            Some(edge) => edge.count += 1,
+            None => edges.push(CallEdge { callee, count: 1, kind }),
         }
-        if !buffer.is_empty() {
-            sections.push(buffer);
-        }
-        sections }
-    )
-        .map(|x| x.iter().map(parse_node).collect::<Vec<_>>())
-        .map(|mut x| {
-            x.sort_unstable_by(|x, y| x.address.cmp(&y.address));
-            x.dedup_by(|x, y |x.address == y.address);
-            serde_json::to_string_pretty(&x).unwrap()
+    }
+    edges
+}
+
+/// Rewrite the call graph `opt` printed to `path` as JSON.
+pub fn process_graph(path: &str) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .map_err(Error::io(format!("failed to read call graph {}", path)))?;
+    let nodes = callgraph::parse(&text)
+        .map_err(|e| Error::Parse(format!("malformed call graph {}: {}", path, e)))?;
+    let declared = nodes.iter()
+        .filter(|x| x.calls.iter().any(|c| c.site.is_none() && c.callee == Callee::External))
+        .filter_map(|x| x.function.clone())
+        .collect::<HashSet<_>>();
+    let mut nodes = nodes.into_iter()
+        .filter_map(|x| {
+            let name = x.function?;
+            Some(GraphNode {
+                defined: !declared.contains(&name) && !name.starts_with("llvm."),
+                name,
+                uses: x.uses,
+                address: x.address.unwrap_or(0),
+                calls: edges(x.calls, &declared),
+                demangled: None,
+                location: None,
+            })
         })
-        .and_then(|x| std::fs::write(path, x))
+        .collect::<Vec<_>>();
+    nodes.sort_by(|x, y| x.name.cmp(&y.name));
+    nodes.dedup_by(|x, y| x.name == y.name);
+    store_graph(path, &nodes)
+}
 
+fn store_graph(path: &str, nodes: &[GraphNode]) -> Result<()> {
+    serde_json::to_string_pretty(nodes)
+        .map_err(|e| Error::Parse(format!("failed to serialize call graph: {}", e)))
+        .and_then(|x| std::fs::write(path, x)
+            .map_err(Error::io(format!("failed to store call graph {}", path))))
 }
 
-pub fn parse_node(section: &Vec<String>) -> GraphNode {
-    let head = section.first().unwrap();
-    let stream = head.split(' ');
-    let mut flag = 0;
-    let mut u : usize = 0;
-    let mut address : usize = 0;
-    let mut name : String = String::new();
-    for w in stream {
-        let w = w.trim();
-        if w.is_empty() {continue; }
-        if flag == 1 {
-            let content : Vec<&str> = w.trim_start_matches('\'').trim_end_matches('>').split("'<<").collect();
-            address = usize::from_str_radix(content[1].trim_start_matches("0x"), 16).unwrap();
-            name = String::from(content[0]);
-        }
-        if flag == 2 {
-            let content = w.trim_start_matches("#uses=");
-            u = content.parse().unwrap();
-            break;
-        }
-        if flag != 0 || w.ends_with(':') {
-            flag += 1;
-        }
+/// Add demangled names and the source locations from the debug info of `module` to the call
+/// graph at `path`. Modules `llvm-dis` cannot read just get no locations.
+pub fn annotate(config: &Configuration, path: &str, module: &str) -> Result<()> {
+    let locations = debuginfo::locations(config.llvm_dis(), module).unwrap_or_else(|e| {
+        warn!("no source locations for {}: {}", module, e);
+        HashMap::new()
+    });
+    if !config.demangle && locations.is_empty() {
+        return Ok(());
     }
-    let k = section[1..]
-        .iter()
-        .filter(|x|!x.contains("external node"))
-        .map(|x| x.split(' ').last().unwrap())
-        .map(|x| String::from(x
-            .trim_matches('\'')))
-        .collect();
-    GraphNode {
-        name,
-        uses: u,
-        address,
-        call_list: k
+    let mut nodes = read_graph(path)?;
+    for x in &mut nodes {
+        if config.demangle {
+            x.demangled = demangle(&x.name);
+        }
+        x.location = locations.get(&x.name).cloned();
     }
+    store_graph(path, &nodes)
 }
-
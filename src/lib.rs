@@ -0,0 +1,32 @@
+use percent_encoding::{AsciiSet, CONTROLS};
+
+pub use crate::config::Configuration;
+pub use crate::error::{Error, Result};
+pub use crate::pipeline::{Pipeline, Progress, Stage};
+
+pub(crate) const FRAGMENT: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'<').add(b'>').add(b'`').add(b'/').add(b'\\');
+
+pub mod cache;
+pub mod callgraph;
+pub mod command;
+pub mod compdb;
+pub mod config;
+pub mod debuginfo;
+pub mod demangle;
+pub mod compile;
+pub mod cmaker;
+pub mod linking;
+pub mod graph;
+pub mod ninja;
+pub mod error;
+pub mod export;
+pub mod filter;
+pub mod project;
+pub mod query;
+pub mod scheduler;
+pub mod shell;
+pub mod symbols;
+pub mod targets;
+pub mod trace;
+mod pipeline;
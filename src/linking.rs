@@ -1,136 +1,139 @@
-use std::ops::Deref;
-use std::sync::Arc;
-use std::sync::atomic::*;
-use std::sync::atomic::Ordering::{Relaxed, SeqCst};
+use std::path::{Path, PathBuf};
 
-use hashbrown::{HashMap, HashSet};
 use log::*;
-use rayon::prelude::*;
+use serde::*;
 
+use crate::cache::{Cache, Job};
 use crate::cmaker::*;
+use crate::error::{Error, Result, Tool};
+use crate::scheduler::{run, Status};
+use crate::symbols;
+use crate::targets::TargetGraph;
+use crate::Pipeline;
 
-#[derive(Copy, Clone)]
-struct Wrapper(*const LinkScript);
-
-unsafe impl Sync for Wrapper {}
-
-unsafe impl Send for Wrapper {}
-
-impl Deref for Wrapper {
-    type Target = LinkScript;
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LinkFailure {
+    pub target: String,
+    pub message: String,
+}
 
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*self.0 }
-    }
+/// Targets by how linking them went, see `Pipeline::with_keep_going`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LinkReport {
+    pub succeeded: Vec<String>,
+    pub failed: Vec<LinkFailure>,
+    pub skipped: Vec<String>,
 }
 
-pub(crate) fn linking(c: Arc<Collection>) {
-    let mut map = HashMap::new();
-    let mut set = HashSet::new();
-    let obj_path = format!("{}/rz_build/objects", *crate::config::PWD);
-    for i in &c.objects {
-        set.insert(i.abs_path.clone());
-    }
-    for i in &c.scripts {
-        map.insert(i.target.abs_path.clone(), (AtomicUsize::new(0), Vec::new()));
+impl LinkReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
     }
+}
 
-    c.scripts.iter().for_each(|x| {
-        for i in &x.target.dependencies {
-            if map.get_mut(i).map(|y| {
-                y.1.push(Wrapper(x as _));
-            }).is_some() {
-                map.get(x.target.abs_path.as_str()).unwrap().0.fetch_add(1, SeqCst);
-            };
+/// Remove the module and call graph an earlier run left of `target`, which failed or was skipped
+/// this time, so that the graph stage does not take them for current.
+fn remove_stale(pipeline: &Pipeline, target: &str) {
+    let encoded = percent_encoding::percent_encode(target.as_bytes(), crate::FRAGMENT).to_string();
+    for path in &[pipeline.object_dir().join(&encoded), pipeline.graph_dir().join(&encoded)] {
+        if std::fs::metadata(path).is_ok() {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("cannot remove stale {}: {}", path.display(), e);
+            }
         }
-    });
+    }
+}
 
-    let map = Arc::new(map);
-    let set = Arc::new(set);
-    let finished = Arc::new(AtomicUsize::new(0));
-    let q = Arc::new(crossbeam::queue::SegQueue::new());
+pub fn linking(pipeline: &Pipeline, c: &Collection) -> Result<LinkReport> {
+    let obj_path = pipeline.object_dir().to_string_lossy().into_owned();
+    let llvm_link = &pipeline.config().llvm_link_executable;
+    let graph = TargetGraph::new(c);
+    let dot = pipeline.build_dir().join("targets.dot");
+    std::fs::write(&dot, graph.to_dot())
+        .map_err(Error::io(format!("failed to store {}", dot.display())))?;
+    info!("target graph saved at {:?}", dot);
+    graph.check(pipeline.config().strict_dependencies)?;
+    symbols::check(pipeline, c, &graph)?;
 
-    c.scripts.par_iter().for_each(|m| {
-        let u = map.get(m.target.abs_path.as_str()).unwrap();
-        if u.0.load(Relaxed) == 0 {
-            q.push(Wrapper(m as _))
+    let cache = Cache::load(pipeline.cache_path())?;
+    let outcome = run("linking", pipeline.jobs(), &graph.schedule(), pipeline.keep_going(), |i| {
+        let link = graph.script(i);
+        let a = obj_path.clone() + "/" +
+            percent_encoding::percent_encode(link.target.abs_path.as_bytes(), crate::FRAGMENT).to_string().as_str();
+        let inputs = graph.inputs(i).into_iter()
+            .map(|x| obj_path.clone() + "/" +
+                percent_encoding::percent_encode(x.as_bytes(), crate::FRAGMENT).to_string().as_str())
+            .collect::<Vec<_>>();
+        let mut command = inputs.clone();
+        command.push(String::from("-o"));
+        command.push(a.clone());
+        let job = Job {
+            output: Path::new(&a),
+            tool: llvm_link,
+            inputs: inputs.iter().map(PathBuf::from).collect(),
+            command: command.clone(),
+            settings: Vec::new(),
+        };
+        if cache.is_fresh(&job) {
+            info!("found {}, using cached", a);
+            return Ok(());
         }
+        let status = std::process::Command::new(llvm_link)
+            .args(&command)
+            .spawn()
+            .and_then(|mut x| x.wait());
+        Error::tool(Tool::LlvmLink, Some(&link.target.abs_path), status)
+            .map(|_| {
+                cache.record(&job);
+                info!("linked {}", link.target.abs_path)
+            })
+            .map_err(|e| {
+                error!("failed to link {}: {}\ncommand: {} {}", link.target.abs_path, e,
+                       llvm_link, command.join(" "));
+                e
+            })
     });
+    cache.store()?;
+    if !pipeline.keep_going() {
+        return outcome.into_result().map(|_| LinkReport {
+            succeeded: c.scripts.iter().map(|x| x.target.abs_path.clone()).collect(),
+            ..LinkReport::default()
+        });
+    }
+    if let Some(e) = outcome.fatal {
+        return Err(e);
+    }
 
-    let mut threads = Vec::new();
-    for _ in 0..num_cpus::get() {
-        let c = c.clone();
-        let obj_path = obj_path.clone();
-        let map = map.clone();
-        let set = set.clone();
-        let finished = finished.clone();
-        let q = q.clone();
-        threads.push(std::thread::spawn(move || {
-            while finished.load(Ordering::Relaxed) != c.scripts.len() {
-                match q.pop() {
-                    Ok(link) => {
-                        info!("linking in progress: {}/{}", finished.load(Ordering::Relaxed), c.scripts.len());
-                        let a = obj_path.clone() + "/" +
-                            percent_encoding::percent_encode(link.target.abs_path.as_bytes(), crate::FRAGMENT).to_string().as_str();
-                        if std::fs::metadata(&a).is_ok() {
-                            info!("found {}, using cached", a);
-                            for i in &map.get(link.target.abs_path.as_str()).unwrap().1 {
-                                for j in &map.get(i.target.abs_path.as_str()) {
-                                    j.0.fetch_sub(1, Ordering::SeqCst);
-                                    if j.0.load(Ordering::SeqCst) == 0 {
-                                        q.push(*i)
-                                    }
-                                }
-                            }
-                            finished.fetch_add(1, Ordering::SeqCst);
-                            info!("linked {}", link.target.abs_path);
-                        } else {
-                            let mut command = link.target.dependencies.iter()
-                                .filter(|x| map.contains_key(x.as_str()) || set.contains(x.as_str()))
-                                .map(|x| obj_path.clone() + "/" +
-                                    percent_encoding::percent_encode(x.as_bytes(), crate::FRAGMENT).to_string().as_str())
-                                .collect::<Vec<_>>();
-                            command.sort_unstable();
-                            command.dedup_by(|x, y| x == y);
-                            command.push(String::from("-o"));
-                            command.push(a);
-                            match std::process::Command::new(&crate::config::CONFIG.llvm_link_executable)
-                                .args(&command)
-                                .spawn()
-                                .and_then(|mut x| x.wait()) {
-                                Ok(e) if e.success() => {
-                                    for i in &map.get(link.target.abs_path.as_str()).unwrap().1 {
-                                        for j in &map.get(i.target.abs_path.as_str()) {
-                                            j.0.fetch_sub(1, Ordering::SeqCst);
-                                            if j.0.load(Ordering::SeqCst) == 0 {
-                                                q.push(*i)
-                                            }
-                                        }
-                                    }
-                                    finished.fetch_add(1, Ordering::SeqCst);
-                                    info!("linked {}", link.target.abs_path);
-                                }
-                                Err(e) => {
-                                    error!("failed to link {}: {:?}\ncommand: {} {}", link.target.abs_path, e,
-                                           crate::config::CONFIG.llvm_link_executable, command.join(" "));
-                                    std::process::exit(50);
-                                }
-                                Ok(c) => {
-                                    error!("failed to link {}: exit with {:?}\ncommand: {} {}", link.target.abs_path, c,
-                                           crate::config::CONFIG.llvm_link_executable, command.join(" "));
-                                    std::process::exit(50);
-                                }
-                            }
-                        }
-                    }
-                    _ => spin_loop_hint()
-                };
-            }
-        }));
+    let mut report = LinkReport::default();
+    for (i, status) in outcome.status.iter().enumerate() {
+        let target = graph.script(i).target.abs_path.clone();
+        if *status != Status::Succeeded {
+            remove_stale(pipeline, &target);
+        }
+        match status {
+            Status::Succeeded => report.succeeded.push(target),
+            Status::Skipped | Status::Pending => report.skipped.push(target),
+            Status::Failed => (),
+        }
     }
-    for i in threads {
-        if i.join().is_err() {
-            error!("failed to join linking handle")
-        };
+    for (i, e) in outcome.errors {
+        report.failed.push(LinkFailure {
+            target: graph.script(i).target.abs_path.clone(),
+            message: e.to_string(),
+        });
+    }
+    let path = pipeline.build_dir().join("link_report.json");
+    serde_json::to_string_pretty(&report)
+        .map_err(|e| Error::Parse(format!("failed to serialize link report: {}", e)))
+        .and_then(|x| std::fs::write(&path, x)
+            .map_err(Error::io(format!("failed to store {}", path.display()))))?;
+    info!("linked {} target(s), {} failed, {} skipped, report saved at {:?}",
+          report.succeeded.len(), report.failed.len(), report.skipped.len(), path);
+    for i in &report.failed {
+        error!("failed: {}", i.target);
+    }
+    for i in &report.skipped {
+        warn!("skipped: {}", i);
     }
+    Ok(report)
 }
@@ -1,134 +1,195 @@
-#[macro_use]
-extern crate lazy_static;
-
-use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::sync::Arc;
 
 use log::*;
-use percent_encoding::{AsciiSet, CONTROLS};
-use serde::*;
+use structopt::StructOpt;
 
-use crate::cmaker::{run_cmake, run_cmaker, run_remake};
+use rzlinkhelper_rs::{Configuration, Error, Pipeline, Stage};
+use rzlinkhelper_rs::config::{CompilePolicy, GraphBackend};
+use rzlinkhelper_rs::export::Format;
+use rzlinkhelper_rs::query::Query;
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
-const FRAGMENT: &AsciiSet = &CONTROLS
-    .add(b' ').add(b'"').add(b'<').add(b'>').add(b'`').add(b'/').add(b'\\');
 
-mod config;
-mod compile;
-mod cmaker;
-mod linking;
-mod graph;
+#[derive(StructOpt)]
+#[structopt(about = "compile a cmake project to llvm bitcode and generate call graphs")]
+struct Opt {
+    /// Config file, defaults to config.json in the work dir
+    #[structopt(long, env = "RZ_CONFIG", global = true)]
+    config: Option<PathBuf>,
+    /// Work dir holding the project, rz_build and .progress
+    #[structopt(long, global = true)]
+    workdir: Option<PathBuf>,
+    /// Number of parallel jobs
+    #[structopt(short, long, global = true)]
+    jobs: Option<usize>,
+    /// Keep linking independent targets after a link failure
+    #[structopt(short, long, global = true)]
+    keep_going: bool,
+    /// What to do when an object fails to compile: fail-fast, keep-going or ignore
+    #[structopt(long, global = true)]
+    compile_policy: Option<CompilePolicy>,
+    /// Only process targets matching this name, path glob or kind:<kind>, with their dependencies
+    #[structopt(short, long = "target", global = true, number_of_values = 1)]
+    targets: Vec<String>,
+    /// Skip targets matching this name, path glob or kind:<kind> unless another target needs them
+    #[structopt(long = "exclude-target", global = true, number_of_values = 1)]
+    exclude_targets: Vec<String>,
+    /// How opt is asked for call graphs: plugin or print-callgraph
+    #[structopt(long, global = true)]
+    graph_backend: Option<GraphBackend>,
+    /// Also export call graphs as dot, graphml or gexf
+    #[structopt(long = "export", global = true, number_of_values = 1)]
+    export_formats: Vec<Format>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
 
-#[derive(Deserialize, Serialize)]
-struct Progress {
-    cmake: bool,
-    remake: bool,
-    cmaker: bool,
-    compile_to_llvm: bool,
-    linking: bool,
-    gen_graph: bool,
+#[derive(StructOpt)]
+enum Command {
+    /// Run all pending stages
+    Run {
+        /// Re-run this stage and every stage after it
+        #[structopt(long, conflicts_with = "only-stage")]
+        from_stage: Option<Stage>,
+        /// Re-run only this stage
+        #[structopt(long)]
+        only_stage: Option<Stage>,
+    },
+    /// Configure the project with cmake
+    Configure,
+    /// Build the project and read objects and targets from its log
+    BuildLog,
+    /// Compile objects to llvm bitcode
+    Compile,
+    /// Link bitcode of every target
+    Link,
+    /// Generate call graphs
+    Graph,
+    /// Show the recorded progress
+    Status,
+    /// Remove rz_build and .progress
+    Clean,
+    /// Ask the call graph of a target, or of the whole project, about functions
+    Query {
+        /// Query the call graph of this target instead of the project graph
+        #[structopt(long = "in")]
+        target: Option<String>,
+        /// Print the answer as JSON
+        #[structopt(long)]
+        json: bool,
+        #[structopt(subcommand)]
+        query: QueryCommand,
+    },
 }
 
-fn load_progress() -> Progress {
-    match std::fs::File::open(config::PWD.clone() + "/.progress") {
-        Ok(mut file) => {
-            let mut buf = String::new();
-            file.read_to_string(&mut buf).unwrap_or_else(|e| {
-                error!("failed to read the progress file, you had better delete it and re-run: {}", e);
-                exit(5)
-            });
-            simd_json::serde::from_str(&mut buf).unwrap_or_else(|e| {
-                error!("failed to parse the progress file, you had better delete it and re-run: {}", e);
-                exit(5)
-            }
-            )
-        }
-        Err(_) => match std::fs::File::create(config::PWD.clone() + "/.progress") {
-            Ok(_) => {
-                Progress {
-                    cmake: false,
-                    remake: false,
-                    cmaker: false,
-                    compile_to_llvm: false,
-                    linking: false,
-                    gen_graph: false,
-                }
-            }
-            Err(e) => {
-                error!("failed to initialize progress record: {}", e);
-                exit(5);
-            }
-        }
-    }
+#[derive(StructOpt)]
+enum QueryCommand {
+    /// Functions calling a function
+    Callers { function: String },
+    /// Functions a function calls
+    Callees { function: String },
+    /// Functions reachable from the roots
+    Reachable {
+        #[structopt(default_value = "main")]
+        roots: Vec<String>,
+    },
+    /// A shortest call path between two functions
+    Path { from: String, to: String },
+    /// Functions unreachable from the roots
+    Dead {
+        #[structopt(default_value = "main")]
+        roots: Vec<String>,
+    },
 }
 
-impl Drop for Progress {
-    fn drop(&mut self) {
-        if !self.cmake {
-            if std::fs::remove_dir_all(config::PWD.clone() + "/rz_build").is_err() {
-                warn!("cannot remove ./rz_build, please check if it is created");
-            }
+impl From<QueryCommand> for Query {
+    fn from(command: QueryCommand) -> Self {
+        match command {
+            QueryCommand::Callers { function } => Query::Callers(function),
+            QueryCommand::Callees { function } => Query::Callees(function),
+            QueryCommand::Reachable { roots } => Query::Reachable(roots),
+            QueryCommand::Path { from, to } => Query::Path(from, to),
+            QueryCommand::Dead { roots } => Query::Dead(roots),
         }
-        serde_json::to_string_pretty(self)
-            .map_err(|e| e.into())
-            .and_then(|c| std::fs::write(config::PWD.clone() + "/.progress", c))
-            .unwrap_or_else(|e|
-                error!("failed to store progress: {}", e)
-            );
     }
 }
 
+fn fail(work_dir: &Path, e: Error) -> ! {
+    error!("{}", e);
+    let summary = work_dir.join(".rz_error.json");
+    serde_json::to_string_pretty(&e.summary())
+        .map_err(|e| e.into())
+        .and_then(|c| std::fs::write(&summary, c))
+        .unwrap_or_else(|e| error!("failed to store error summary: {}", e));
+    exit(e.exit_code())
+}
+
 fn main() {
+    let opt = Opt::from_args();
     std::env::set_var("RUST_LOG", "trace");
     pretty_env_logger::init_timed();
-    info!("work path: {:#?}", *config::PWD);
-    info!("config file: {:#?}", *config::CONFIG);
-    let build_dir = config::PWD.clone() + "/rz_build";
-    let mut progress = load_progress();
-    if !progress.cmake {
-        run_cmake();
-        progress.cmake = true;
+    let work_dir = opt.workdir.map(Ok).unwrap_or_else(std::env::current_dir)
+        .and_then(std::fs::canonicalize)
+        .unwrap_or_else(|e| {
+            error!("cannot get work dir {}", e);
+            exit(2)
+        });
+    info!("work path: {:#?}", work_dir);
+    let mut config = match &opt.config {
+        Some(path) => Configuration::from_file(path),
+        None => Configuration::locate(&work_dir),
+    }.unwrap_or_else(|e| fail(&work_dir, e));
+    if let Some(policy) = opt.compile_policy {
+        config.compile_policy = policy;
     }
-    if !progress.remake {
-        std::env::set_current_dir(&build_dir).unwrap_or_else(|e|
-            {
-                error!("failed to change dir to ./rz_build, remove .progress if you need: {}", e);
-                exit(6);
-            }
-        );
-        run_remake();
-        progress.remake = true;
+    if let Some(backend) = opt.graph_backend {
+        config.graph_backend = backend;
     }
-    if !progress.cmaker {
-        std::env::set_current_dir(&build_dir).unwrap_or_else(|e|
-            {
-                error!("failed to change dir to ./rz_build, remove .progress if you need: {}", e);
-                exit(6);
-            }
-        );
-        run_cmaker();
-        progress.cmaker = true;
+    if !opt.targets.is_empty() {
+        config.include_targets = opt.targets;
     }
-
-    let collection = Arc::new(cmaker::get_collection(&build_dir));
-    if !progress.compile_to_llvm {
-        info!("start compiling to llvm");
-        compile::compile_to_llvm(collection.as_ref());
-        progress.compile_to_llvm = true;
+    if !opt.exclude_targets.is_empty() {
+        config.exclude_targets = opt.exclude_targets;
     }
-    if !progress.linking {
-        info!("start linking");
-        linking::linking(collection.clone());
-        progress.linking = true;
+    if !opt.export_formats.is_empty() {
+        config.export_formats = opt.export_formats;
     }
-
-    if !progress.gen_graph {
-        info!("start generating call graph");
-        graph::gen_graph(collection.as_ref());
-        progress.gen_graph = true;
+    info!("config file: {:#?}", config);
+    let mut pipeline = Pipeline::new(config, &work_dir)
+        .unwrap_or_else(|e| fail(&work_dir, e))
+        .with_keep_going(opt.keep_going);
+    if let Some(jobs) = opt.jobs {
+        pipeline = pipeline.with_jobs(jobs);
+    }
+    let result = match opt.command.unwrap_or(Command::Run { from_stage: None, only_stage: None }) {
+        Command::Run { from_stage: Some(stage), .. } => pipeline.run_from(stage),
+        Command::Run { only_stage: Some(stage), .. } => pipeline.run_stages(&[stage]),
+        Command::Run { .. } => pipeline.run(),
+        Command::Configure => pipeline.run_stages(&[Stage::Cmake]),
+        Command::BuildLog => pipeline.run_stages(&[Stage::Remake, Stage::Cmaker]),
+        Command::Compile => pipeline.run_stages(&[Stage::Compile]),
+        Command::Link => pipeline.run_stages(&[Stage::Link]),
+        Command::Graph => pipeline.run_stages(&[Stage::Graph]),
+        Command::Status => pipeline.load_progress().map(|progress| {
+            for stage in Stage::ALL.iter() {
+                println!("{:<8} {}", stage, if progress.is_done(*stage) { "done" } else { "pending" });
+            }
+        }),
+        Command::Clean => pipeline.clean(),
+        Command::Query { target, json, query } => {
+            let query = Query::from(query);
+            pipeline.query(target.as_deref(), &query).map(|answer| if json {
+                println!("{}", serde_json::to_string_pretty(&answer).unwrap());
+            } else {
+                println!("{}", answer.to_text(&query));
+            })
+        }
+    };
+    if let Err(e) = result {
+        fail(&work_dir, e);
     }
-    info!("all processes finished, if you want to re-run please delete the rz_build dir and the .progress file");
+    let _ = std::fs::remove_file(work_dir.join(".rz_error.json"));
+    info!("all processes finished");
 }
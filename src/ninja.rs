@@ -0,0 +1,86 @@
+//! Build with Ninja and read objects, link scripts and compile commands back from it.
+
+use std::process::Command;
+
+use log::*;
+
+use crate::cmaker::{self, run_captured, Collection, LinkScript, Object};
+use crate::command::CompileCommand;
+use crate::compdb;
+use crate::error::{Error, Result, Tool};
+use crate::shell;
+use crate::symbols;
+use crate::trace::{file_name, link_target};
+use crate::Pipeline;
+
+pub fn run_ninja(pipeline: &Pipeline) -> Result<()> {
+    let cpu = pipeline.jobs();
+    info!("start building with {} thread(s). ", cpu);
+    let build_dir = pipeline.build_dir();
+    let res = run_captured(Tool::Ninja, Command::new(pipeline.config().ninja())
+        .current_dir(&build_dir)
+        .arg(format!("-j{}", cpu)))?;
+    let log = build_dir.join("ninja.log");
+    std::fs::write(&log, res.as_bytes())
+        .map_err(Error::io("failed to store ninja log"))?;
+    info!("ninja log saved at {:?}", log);
+    Ok(())
+}
+
+/// Query ninja for compile and link commands and store them as `cmaker.log`.
+pub fn extract(pipeline: &Pipeline) -> Result<()> {
+    let config = pipeline.config();
+    let build_dir = pipeline.build_dir();
+    let compilers = [config.original_cxx_executable.as_str(), config.original_cc_executable.as_str()];
+
+    let compdb = run_captured(Tool::Ninja, Command::new(config.ninja())
+        .current_dir(&build_dir)
+        .args(["-t", "compdb"]))?;
+    let entries: Vec<compdb::Entry> = simd_json::serde::from_str(&mut compdb.clone())
+        .map_err(|e| Error::Parse(format!("failed to parse ninja compdb: {}", e)))?;
+    let mut compile = Vec::new();
+    let mut objects = Vec::new();
+    for entry in entries {
+        if !entry.is_compiled_by(&compilers)? {
+            continue;
+        }
+        let line = entry.to_compile()?;
+        let command = CompileCommand::parse(&line, &compilers)?;
+        objects.push(Object {
+            name: file_name(&command.output),
+            abs_path: command.object(),
+            defined_symbols: Vec::new(),
+            undefined_symbols: Vec::new(),
+        });
+        compile.push(line);
+    }
+
+    let commands = run_captured(Tool::Ninja, Command::new(config.ninja())
+        .current_dir(&build_dir)
+        .args(["-t", "commands"]))?;
+    let mut scripts = Vec::new();
+    for line in commands.lines() {
+        let mut cwd = build_dir.clone();
+        for words in shell::commands(line)? {
+            if words[0] == "cd" && words.len() > 1 {
+                cwd = cwd.join(&words[1]);
+            } else if let Some(target) = link_target(&cwd, words, &compilers)? {
+                trace!("found target {} linking {:?}", target.abs_path, target.dependencies);
+                scripts.push(LinkScript {
+                    abs_path: target.abs_path.clone(),
+                    target,
+                });
+                break;
+            }
+        }
+    }
+    info!("found {} object(s) and {} target(s) in ninja build", objects.len(), scripts.len());
+
+    let mut collection = Collection {
+        objects,
+        scripts,
+        compile,
+    };
+    symbols::fill(pipeline, &mut collection)?;
+    cmaker::store_collection(&collection, build_dir.join("cmaker.log"))
+}
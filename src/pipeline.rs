@@ -0,0 +1,385 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use hashbrown::HashSet;
+use log::*;
+use serde::*;
+
+use crate::cmaker::{self, Collection};
+use crate::compdb;
+use crate::compile::{self, CompileReport};
+use crate::config::{BuildBackend, Configuration};
+use crate::error::{Error, Result, Tool};
+use crate::export;
+use crate::filter::TargetFilter;
+use crate::graph;
+use crate::linking::{self, LinkReport};
+use crate::ninja;
+use crate::project::{self, ProjectGraph};
+use crate::query::{self, Answer, Query};
+use crate::trace;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Progress {
+    pub cmake: bool,
+    pub remake: bool,
+    pub cmaker: bool,
+    pub compile_to_llvm: bool,
+    pub linking: bool,
+    pub gen_graph: bool,
+    /// Objects that failed to compile in the last compile stage, retried first on the next run.
+    #[serde(default)]
+    pub failed_objects: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Cmake,
+    Remake,
+    Cmaker,
+    Compile,
+    Link,
+    Graph,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [Stage::Cmake, Stage::Remake, Stage::Cmaker, Stage::Compile, Stage::Link, Stage::Graph];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Cmake => "cmake",
+            Stage::Remake => "remake",
+            Stage::Cmaker => "cmaker",
+            Stage::Compile => "compile",
+            Stage::Link => "link",
+            Stage::Graph => "graph",
+        }
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Stage::ALL.iter().copied().find(|x| x.name() == s)
+            .ok_or_else(|| format!("unknown stage {}, expected one of: cmake, remake, cmaker, compile, link, graph", s))
+    }
+}
+
+impl Progress {
+    pub fn is_done(&self, stage: Stage) -> bool {
+        match stage {
+            Stage::Cmake => self.cmake,
+            Stage::Remake => self.remake,
+            Stage::Cmaker => self.cmaker,
+            Stage::Compile => self.compile_to_llvm,
+            Stage::Link => self.linking,
+            Stage::Graph => self.gen_graph,
+        }
+    }
+
+    pub fn set(&mut self, stage: Stage, done: bool) {
+        match stage {
+            Stage::Cmake => self.cmake = done,
+            Stage::Remake => self.remake = done,
+            Stage::Cmaker => self.cmaker = done,
+            Stage::Compile => self.compile_to_llvm = done,
+            Stage::Link => self.linking = done,
+            Stage::Graph => self.gen_graph = done,
+        }
+    }
+
+    /// Mark `stage` and every later stage as pending. Re-running the compile stage or an earlier
+    /// one compiles every object again, not just those that failed last time.
+    pub fn reset_from(&mut self, stage: Stage) {
+        for i in Stage::ALL.iter().filter(|x| **x >= stage) {
+            self.set(*i, false);
+        }
+        if stage <= Stage::Compile {
+            self.failed_objects.clear();
+        }
+    }
+}
+
+/// The helper's stages bound to one configuration and work directory.
+#[derive(Debug)]
+pub struct Pipeline {
+    config: Configuration,
+    work_dir: PathBuf,
+    jobs: usize,
+    keep_going: bool,
+}
+
+impl Pipeline {
+    /// A pipeline working in `work_dir`, resolved against the current directory if relative since
+    /// the stages run their tools from inside the build.
+    pub fn new<P: Into<PathBuf>>(config: Configuration, work_dir: P) -> Result<Self> {
+        let work_dir = work_dir.into();
+        let work_dir = if work_dir.is_absolute() {
+            work_dir
+        } else {
+            std::env::current_dir()
+                .map_err(Error::io(format!("cannot resolve work dir {}", work_dir.display())))?
+                .join(work_dir)
+        };
+        let jobs = config.jobs.unwrap_or_else(num_cpus::get).max(1);
+        Ok(Pipeline {
+            config,
+            work_dir,
+            jobs,
+            keep_going: false,
+        })
+    }
+
+    /// Set how many external processes may run at once, overriding the config.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Keep linking independent targets after one fails, skipping its dependents.
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    pub fn keep_going(&self) -> bool {
+        self.keep_going
+    }
+
+    pub fn config(&self) -> &Configuration {
+        &self.config
+    }
+
+    pub fn work_dir(&self) -> &Path {
+        &self.work_dir
+    }
+
+    pub fn build_dir(&self) -> PathBuf {
+        self.work_dir.join("rz_build")
+    }
+
+    pub fn object_dir(&self) -> PathBuf {
+        self.build_dir().join("objects")
+    }
+
+    pub fn graph_dir(&self) -> PathBuf {
+        self.build_dir().join("graph")
+    }
+
+    pub fn project_graph_path(&self) -> PathBuf {
+        self.build_dir().join("project_graph.json")
+    }
+
+    pub fn cache_path(&self) -> PathBuf {
+        self.build_dir().join("cache.json")
+    }
+
+    pub fn progress_path(&self) -> PathBuf {
+        self.work_dir.join(".progress")
+    }
+
+    pub fn load_progress(&self) -> Result<Progress> {
+        let path = self.progress_path();
+        if std::fs::metadata(&path).is_err() {
+            return Ok(Progress::default());
+        }
+        std::fs::read_to_string(&path)
+            .map_err(Error::io("failed to read the progress file, you had better delete it and re-run"))
+            .and_then(|mut buf| simd_json::serde::from_str(&mut buf)
+                .map_err(|e| Error::Parse(
+                    format!("failed to parse the progress file, you had better delete it and re-run: {}", e))))
+    }
+
+    pub fn store_progress(&self, progress: &Progress) -> Result<()> {
+        serde_json::to_string_pretty(progress)
+            .map_err(|e| Error::Parse(format!("failed to serialize progress: {}", e)))
+            .and_then(|c| std::fs::write(self.progress_path(), c)
+                .map_err(Error::io("failed to store progress")))
+    }
+
+    pub fn run_cmake(&self) -> Result<()> {
+        cmaker::run_cmake(self)
+    }
+
+    pub fn run_remake(&self) -> Result<()> {
+        match self.config.build_backend {
+            BuildBackend::Make => cmaker::run_remake(self),
+            BuildBackend::Ninja => ninja::run_ninja(self),
+        }
+    }
+
+    pub fn run_cmaker(&self) -> Result<()> {
+        match self.config.build_backend {
+            BuildBackend::Make => trace::extract(self),
+            BuildBackend::Ninja => ninja::extract(self),
+        }
+    }
+
+    /// Load objects and link scripts from `link_collection` or the cmaker log, and the
+    /// compile list from `compile_commands` if set, keeping only what the target filters select.
+    pub fn collection(&self) -> Result<Collection> {
+        let mut collection = match &self.config.link_collection {
+            Some(path) => cmaker::read_collection(self.work_dir.join(path))?,
+            None => cmaker::get_collection(self)?,
+        };
+        let compilers = [self.config.original_cxx_executable.as_str(), self.config.original_cc_executable.as_str()];
+        if let Some(path) = &self.config.compile_commands {
+            collection.compile = compdb::compile_list(self.work_dir.join(path), &compilers)?;
+        }
+        self.filter()?.apply(collection, &compilers)
+    }
+
+    pub fn filter(&self) -> Result<TargetFilter> {
+        TargetFilter::new(&self.config.include_targets, &self.config.exclude_targets)
+    }
+
+    pub fn compile(&self, collection: &Collection, only: Option<&HashSet<String>>) -> Result<CompileReport> {
+        compile::compile_to_llvm(self, collection, only)
+    }
+
+    pub fn link(&self, collection: &Collection) -> Result<LinkReport> {
+        linking::linking(self, collection)
+    }
+
+    /// Generate the call graph of every target, then merge them into the project graph.
+    pub fn gen_graph(&self, collection: &Collection) -> Result<()> {
+        graph::gen_graph(self, collection)?;
+        let path = self.project_graph_path();
+        let project = project::aggregate(self, collection)?;
+        project.store(&path)?;
+        info!("project call graph saved at {:?}", path);
+        export::export(self, collection, &project)
+    }
+
+    /// Answer `query` from the call graph of `target`, named by name or path, or else from the
+    /// project graph.
+    pub fn query(&self, target: Option<&str>, query: &Query) -> Result<Answer> {
+        match target {
+            Some(target) => {
+                let collection = self.collection()?;
+                let script = collection.scripts.iter()
+                    .find(|x| x.target.name == target || x.target.abs_path == target)
+                    .ok_or_else(|| Error::Query(format!("no target named {}", target)))?;
+                let encoded = percent_encoding::percent_encode(script.target.abs_path.as_bytes(), crate::FRAGMENT);
+                let nodes = graph::read_graph(self.graph_dir().join(encoded.to_string()))?;
+                query::answer(&export::Graph::from_nodes(&nodes), query)
+            }
+            None => {
+                let project = ProjectGraph::load(self.project_graph_path())?;
+                query::answer(&export::Graph::from(&project), query)
+            }
+        }
+    }
+
+    /// Remove the build directory and the progress record.
+    pub fn clean(&self) -> Result<()> {
+        if std::fs::metadata(self.build_dir()).is_ok() {
+            std::fs::remove_dir_all(self.build_dir())
+                .map_err(Error::io("failed to remove ./rz_build"))?;
+        }
+        if std::fs::metadata(self.progress_path()).is_ok() {
+            std::fs::remove_file(self.progress_path())
+                .map_err(Error::io("failed to remove .progress"))?;
+        }
+        Ok(())
+    }
+
+    /// Run `stages` in order regardless of `.progress`, recording each one as done.
+    pub fn run_stages(&self, stages: &[Stage]) -> Result<()> {
+        let mut progress = self.load_progress()?;
+        let mut collection = None;
+        let mut partial = None;
+        let filtered = !self.filter()?.is_empty();
+        for stage in stages {
+            info!("running stage {}", stage);
+            match stage {
+                Stage::Cmake => if let Err(e) = self.run_cmake() {
+                    if std::fs::remove_dir_all(self.build_dir()).is_err() {
+                        warn!("cannot remove ./rz_build, please check if it is created");
+                    }
+                    progress.reset_from(Stage::Cmake);
+                    self.store_progress(&progress)?;
+                    return Err(e);
+                },
+                Stage::Remake | Stage::Cmaker if self.config.link_collection.is_some() =>
+                    info!("link information comes from {:?}, skipping stage {}",
+                          self.config.link_collection.as_ref().unwrap(), stage),
+                Stage::Remake => self.run_remake()?,
+                Stage::Cmaker => self.run_cmaker()?,
+                Stage::Compile | Stage::Link | Stage::Graph => {
+                    if collection.is_none() {
+                        collection.replace(self.collection()?);
+                    }
+                    let collection = collection.as_ref().unwrap();
+                    match stage {
+                        Stage::Compile => {
+                            let retry = progress.failed_objects.iter().cloned().collect::<HashSet<_>>();
+                            if !retry.is_empty() {
+                                info!("retrying {} object(s) that failed to compile", retry.len());
+                            }
+                            let report = self.compile(collection, Some(&retry).filter(|x| !x.is_empty()))?;
+                            progress.failed_objects = report.failed.clone();
+                            self.store_progress(&progress)?;
+                            report.into_result(self.config.compile_policy)?;
+                        }
+                        Stage::Link => {
+                            let report = self.link(collection)?;
+                            if !report.is_complete() {
+                                partial.replace(report);
+                            }
+                        }
+                        _ => self.gen_graph(collection)?,
+                    }
+                }
+            }
+            // stages after a partial link stay pending so that a re-run retries the failed targets,
+            // and stages run on a subset of the targets stay pending for the others
+            if filtered && *stage >= Stage::Compile {
+                info!("stage {} ran on selected targets only, leaving it pending", stage);
+            } else if partial.is_none() {
+                progress.set(*stage, true);
+                self.store_progress(&progress)?;
+            }
+        }
+        match partial.and_then(|x| {
+            let skipped = x.skipped.len();
+            x.failed.into_iter().next().map(|f| (f, skipped))
+        }) {
+            Some((failure, skipped)) => Err(Error::Tool {
+                tool: Tool::LlvmLink,
+                target: Some(failure.target),
+                message: format!("{}, {} dependent target(s) skipped", failure.message, skipped),
+            }),
+            None => Ok(())
+        }
+    }
+
+    /// Run every stage not yet recorded in `.progress`, storing progress after each one.
+    pub fn run(&self) -> Result<()> {
+        let progress = self.load_progress()?;
+        let pending = Stage::ALL.iter().copied()
+            .filter(|x| !progress.is_done(*x))
+            .collect::<Vec<_>>();
+        self.run_stages(&pending)
+    }
+
+    /// Forget the progress of `stage` and everything after it, then run all pending stages.
+    pub fn run_from(&self, stage: Stage) -> Result<()> {
+        let mut progress = self.load_progress()?;
+        progress.reset_from(stage);
+        self.store_progress(&progress)?;
+        self.run()
+    }
+}
@@ -0,0 +1,162 @@
+//! A whole-program call graph merged from the per-target call graphs.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use hashbrown::{HashMap, HashSet};
+use log::*;
+use percent_encoding::percent_encode;
+use serde::*;
+
+use crate::cmaker::Collection;
+use crate::debuginfo::Location;
+use crate::error::{Error, Result};
+use crate::graph::{read_graph, GraphNode};
+use crate::targets::TargetGraph;
+use crate::Pipeline;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectNode {
+    pub name: String,
+    /// Target whose own objects define the function, `None` if no target does.
+    pub target: Option<String>,
+    /// Number of distinct callers.
+    pub uses: usize,
+    /// Indices of the called nodes.
+    pub calls: Vec<usize>,
+    #[serde(default)]
+    pub demangled: Option<String>,
+    #[serde(default)]
+    pub location: Option<Location>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProjectGraph {
+    pub nodes: Vec<ProjectNode>,
+}
+
+impl ProjectGraph {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ProjectGraph> {
+        let path = path.as_ref();
+        std::fs::read_to_string(path)
+            .map_err(Error::io(format!("failed to read {}", path.display())))
+            .and_then(|mut x| simd_json::serde::from_str(&mut x)
+                .map_err(|e| Error::Parse(format!("failed to parse {}: {}", path.display(), e))))
+    }
+
+    pub fn store<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        serde_json::to_string_pretty(self)
+            .map_err(|e| Error::Parse(format!("failed to serialize project graph: {}", e)))
+            .and_then(|x| std::fs::write(path, x)
+                .map_err(Error::io(format!("failed to store {}", path.display()))))
+    }
+}
+
+/// Call graph of target `i`, if one was generated.
+fn read_module(pipeline: &Pipeline, graph: &TargetGraph, i: usize) -> Result<Option<Vec<GraphNode>>> {
+    let target = &graph.script(i).target.abs_path;
+    let path = pipeline.graph_dir().join(percent_encode(target.as_bytes(), crate::FRAGMENT).to_string());
+    if std::fs::metadata(&path).is_err() {
+        return Ok(None);
+    }
+    read_graph(&path).map(Some)
+}
+
+/// Target `i` followed by the targets it links, nearest first.
+fn search_order(graph: &TargetGraph, i: usize) -> Vec<usize> {
+    let mut order = vec![i];
+    let mut seen = vec![false; graph.len()];
+    seen[i] = true;
+    let mut next = 0;
+    while next < order.len() {
+        for &j in graph.dependencies(order[next]) {
+            if !seen[j] {
+                seen[j] = true;
+                order.push(j);
+            }
+        }
+        next += 1;
+    }
+    order
+}
+
+/// Merge the call graphs of all targets in `collection`.
+///
+/// Each function is owned by the target whose own objects define it, looked up from the calling
+/// target through the targets it links. Without symbol information a target is taken to define
+/// the functions its call graph has the bodies of.
+pub fn aggregate(pipeline: &Pipeline, collection: &Collection) -> Result<ProjectGraph> {
+    let graph = TargetGraph::new(collection);
+    let modules = (0..graph.len())
+        .map(|i| read_module(pipeline, &graph, i))
+        .collect::<Result<Vec<_>>>()?;
+    let bodies = modules.iter()
+        .map(|m| m.iter().flatten()
+            .filter(|x| x.defined)
+            .map(|x| x.name.as_str())
+            .collect::<HashSet<_>>())
+        .collect::<Vec<_>>();
+    let own = (0..graph.len())
+        .map(|i| {
+            let defined = graph.inputs(i).into_iter()
+                .filter_map(|x| graph.object(x))
+                .flat_map(|x| x.defined_symbols.iter().map(|s| s.name.as_str()))
+                .collect::<HashSet<_>>();
+            Some(defined).filter(|x| !x.is_empty())
+        })
+        .collect::<Vec<_>>();
+    let defines = |i: usize, name: &str| match &own[i] {
+        Some(defined) => defined.contains(name),
+        None => bodies[i].contains(name),
+    };
+
+    let mut index: HashMap<(Option<usize>, &str), usize> = HashMap::new();
+    let mut keys = Vec::new();
+    let mut edges = BTreeSet::new();
+    // the module node each function takes its demangled name and location from
+    let mut sources: HashMap<usize, &GraphNode> = HashMap::new();
+    for (t, module) in modules.iter().enumerate() {
+        let module = match module {
+            Some(module) => module,
+            None => continue
+        };
+        let order = search_order(&graph, t);
+        let mut node = |name| -> usize {
+            let owner = order.iter().copied().find(|x| defines(*x, name));
+            *index.entry((owner, name)).or_insert_with(|| {
+                keys.push((owner, name));
+                keys.len() - 1
+            })
+        };
+        for function in module.iter().filter(|x| !x.name.is_empty()) {
+            let from = node(function.name.as_str());
+            let source = sources.entry(from).or_insert(function);
+            if source.location.is_none() {
+                *source = function;
+            }
+            for callee in function.callees() {
+                let to = node(callee);
+                edges.insert((from, to));
+            }
+        }
+    }
+
+    let mut nodes = keys.into_iter().enumerate()
+        .map(|(i, (owner, name))| ProjectNode {
+            name: String::from(name),
+            target: owner.map(|x| graph.script(x).target.abs_path.clone()),
+            uses: 0,
+            calls: Vec::new(),
+            demangled: sources.get(&i).and_then(|x| x.demangled.clone()),
+            location: sources.get(&i).and_then(|x| x.location.clone()),
+        })
+        .collect::<Vec<_>>();
+    for (from, to) in edges {
+        nodes[from].calls.push(to);
+        nodes[to].uses += 1;
+    }
+    let external = nodes.iter().filter(|x| x.target.is_none()).count();
+    info!("project call graph has {} function(s), {} of them outside every target", nodes.len(), external);
+    Ok(ProjectGraph { nodes })
+}
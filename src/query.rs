@@ -0,0 +1,160 @@
+//! Questions about a call graph: callers, callees, reachability, call paths and dead functions.
+
+use std::collections::VecDeque;
+
+use serde::*;
+
+use crate::error::{Error, Result};
+use crate::export::Graph;
+
+#[derive(Debug, Clone)]
+pub enum Query {
+    Callers(String),
+    Callees(String),
+    /// Functions reachable from any of the roots, the roots included.
+    Reachable(Vec<String>),
+    /// A shortest call path from the first function to the second.
+    Path(String, String),
+    /// Defined functions not reachable from any of the roots.
+    Dead(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Function {
+    pub name: String,
+    pub target: Option<String>,
+}
+
+/// The functions answering a query; for `Query::Path` the path in call order, empty if there is none.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Answer {
+    pub functions: Vec<Function>,
+}
+
+impl Answer {
+    pub fn to_text(&self, query: &Query) -> String {
+        let name = |x: &Function| match &x.target {
+            Some(target) => format!("{} ({})", x.name, target),
+            None => x.name.clone(),
+        };
+        match query {
+            Query::Path(from, to) if self.functions.is_empty() => format!("no call path from {} to {}", from, to),
+            Query::Path(..) => self.functions.iter().map(name).collect::<Vec<_>>().join("\n -> "),
+            _ => self.functions.iter().map(name).collect::<Vec<_>>().join("\n"),
+        }
+    }
+}
+
+struct Index<'g, 'a> {
+    graph: &'g Graph<'a>,
+    callees: Vec<Vec<usize>>,
+    callers: Vec<Vec<usize>>,
+}
+
+impl<'g, 'a> Index<'g, 'a> {
+    fn new(graph: &'g Graph<'a>) -> Self {
+        let mut callees = vec![Vec::new(); graph.nodes.len()];
+        let mut callers = vec![Vec::new(); graph.nodes.len()];
+        for (from, to) in &graph.edges {
+            callees[*from].push(*to);
+            callers[*to].push(*from);
+        }
+        Index { graph, callees, callers }
+    }
+
+    /// Every node named `name`; a function may be defined in more than one target.
+    fn find(&self, name: &str) -> Result<Vec<usize>> {
+        let found = (0..self.graph.nodes.len())
+            .filter(|x| self.graph.nodes[*x].name == name)
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            return Err(Error::Query(format!("no function named {} in the call graph", name)));
+        }
+        Ok(found)
+    }
+
+    fn find_all(&self, names: &[String]) -> Result<Vec<usize>> {
+        names.iter().map(|x| self.find(x)).collect::<Result<Vec<_>>>()
+            .map(|x| x.into_iter().flatten().collect())
+    }
+
+    /// Number of calls from the root of the search to `node`.
+    fn depth(parent: &[Option<usize>], mut node: usize) -> usize {
+        let mut depth = 0;
+        while parent[node] != Some(node) {
+            node = parent[node].unwrap();
+            depth += 1;
+        }
+        depth
+    }
+
+    /// Breadth-first search along `next` from `roots`, recording each node's predecessor.
+    fn search(&self, roots: &[usize], next: &[Vec<usize>]) -> Vec<Option<usize>> {
+        let mut parent = vec![None; self.graph.nodes.len()];
+        let mut queue = roots.iter().copied().collect::<VecDeque<_>>();
+        for root in roots {
+            parent[*root] = Some(*root);
+        }
+        while let Some(node) = queue.pop_front() {
+            for &x in &next[node] {
+                if parent[x].is_none() {
+                    parent[x] = Some(node);
+                    queue.push_back(x);
+                }
+            }
+        }
+        parent
+    }
+
+    fn function(&self, i: usize) -> Function {
+        let node = &self.graph.nodes[i];
+        Function {
+            name: String::from(node.name),
+            target: node.target.map(String::from),
+        }
+    }
+
+    fn functions<I: IntoIterator<Item=usize>>(&self, nodes: I) -> Vec<Function> {
+        let mut functions = nodes.into_iter().map(|x| self.function(x)).collect::<Vec<_>>();
+        functions.sort_unstable();
+        functions.dedup();
+        functions
+    }
+}
+
+pub fn answer(graph: &Graph, query: &Query) -> Result<Answer> {
+    let index = Index::new(graph);
+    let functions = match query {
+        Query::Callers(name) => index.functions(index.find(name)?.into_iter()
+            .flat_map(|x| index.callers[x].iter().copied())),
+        Query::Callees(name) => index.functions(index.find(name)?.into_iter()
+            .flat_map(|x| index.callees[x].iter().copied())),
+        Query::Reachable(roots) => {
+            let parent = index.search(&index.find_all(roots)?, &index.callees);
+            index.functions((0..parent.len()).filter(|x| parent[*x].is_some()))
+        }
+        Query::Dead(roots) => {
+            let parent = index.search(&index.find_all(roots)?, &index.callees);
+            index.functions((0..parent.len()).filter(|x| parent[*x].is_none() && graph.nodes[*x].defined))
+        }
+        Query::Path(from, to) => {
+            let parent = index.search(&index.find(from)?, &index.callees);
+            // of the functions named `to`, the one closest to `from`
+            let closest = index.find(to)?.into_iter()
+                .filter(|x| parent[*x].is_some())
+                .min_by_key(|x| Index::depth(&parent, *x));
+            match closest {
+                Some(mut node) => {
+                    let mut path = vec![node];
+                    while parent[node] != Some(node) {
+                        node = parent[node].unwrap();
+                        path.push(node);
+                    }
+                    path.into_iter().rev().map(|x| index.function(x)).collect()
+                }
+                None => Vec::new()
+            }
+        }
+    };
+    Ok(Answer { functions })
+}
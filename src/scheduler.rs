@@ -0,0 +1,201 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+use log::*;
+use serde::*;
+
+use crate::error::{Error, Result};
+
+/// Dependencies between the tasks of one stage, stored as indices.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    dependents: Vec<Vec<usize>>,
+    pending: Vec<usize>,
+}
+
+impl Schedule {
+    /// A schedule of `len` tasks without any dependency.
+    pub fn new(len: usize) -> Self {
+        Schedule {
+            dependents: vec![Vec::new(); len],
+            pending: vec![0; len],
+        }
+    }
+
+    /// Make `task` wait until `dependency` has finished.
+    pub fn add_dependency(&mut self, task: usize, dependency: usize) {
+        self.dependents[dependency].push(task);
+        self.pending[task] += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Pending,
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// What happened to every task of a schedule.
+#[derive(Debug)]
+pub struct Outcome {
+    pub status: Vec<Status>,
+    /// Failures in the order they happened.
+    pub errors: Vec<(usize, Error)>,
+    /// Set when the schedule itself could not complete.
+    pub fatal: Option<Error>,
+}
+
+impl Outcome {
+    /// The fatal error or else the first task failure.
+    pub fn into_result(self) -> Result<()> {
+        match (self.fatal, self.errors.into_iter().next()) {
+            (Some(e), _) | (None, Some((_, e))) => Err(e),
+            (None, None) => Ok(())
+        }
+    }
+}
+
+struct State {
+    ready: VecDeque<usize>,
+    pending: Vec<usize>,
+    running: usize,
+    finished: usize,
+    status: Vec<Status>,
+    errors: Vec<(usize, Error)>,
+    fatal: Option<Error>,
+    /// Set when a task panicked, the panic is resumed once every worker stopped.
+    panicked: bool,
+}
+
+/// Gives the slot of a running task back if the task panics, so that the other workers do not
+/// wait for it forever.
+struct Slot<'a> {
+    state: &'a Mutex<State>,
+    wakeup: &'a Condvar,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            let mut guard = self.state.lock().unwrap();
+            guard.running -= 1;
+            guard.panicked = true;
+            self.wakeup.notify_all();
+        }
+    }
+}
+
+/// Run `op` on every task of `schedule` with at most `jobs` tasks at once.
+///
+/// A task starts only after all of its dependencies finished; idle workers sleep until
+/// one does. The first failure stops dispatching and is returned once running tasks end; a
+/// panicking task stops it as well and the panic is resumed in the caller.
+pub fn execute<F>(stage: &str, jobs: usize, schedule: &Schedule, op: F) -> Result<()>
+    where F: Fn(usize) -> Result<()> + Sync {
+    run(stage, jobs, schedule, false, op).into_result()
+}
+
+/// Like `execute`, but with `keep_going` a failed task only skips the tasks depending on it.
+pub fn run<F>(stage: &str, jobs: usize, schedule: &Schedule, keep_going: bool, op: F) -> Outcome
+    where F: Fn(usize) -> Result<()> + Sync {
+    let total = schedule.len();
+    let state = Mutex::new(State {
+        ready: (0..total).filter(|x| schedule.pending[*x] == 0).collect(),
+        pending: schedule.pending.clone(),
+        running: 0,
+        finished: 0,
+        status: vec![Status::Pending; total],
+        errors: Vec::new(),
+        fatal: None,
+        panicked: false,
+    });
+    let wakeup = Condvar::new();
+    let worker = || {
+        let mut guard = state.lock().unwrap();
+        loop {
+            if guard.panicked || guard.fatal.is_some() || guard.finished == total
+                || (!keep_going && !guard.errors.is_empty()) {
+                break;
+            }
+            let task = match guard.ready.pop_front() {
+                Some(task) => task,
+                None if guard.running == 0 => {
+                    let stuck = total - guard.finished;
+                    guard.fatal.replace(Error::Graph(format!(
+                        "{}: {} task(s) can never become ready", stage, stuck)));
+                    wakeup.notify_all();
+                    break;
+                }
+                None => {
+                    guard = wakeup.wait(guard).unwrap();
+                    continue;
+                }
+            };
+            guard.running += 1;
+            drop(guard);
+            let slot = Slot { state: &state, wakeup: &wakeup };
+            let result = op(task);
+            drop(slot);
+            guard = state.lock().unwrap();
+            guard.running -= 1;
+            guard.finished += 1;
+            match result {
+                Ok(()) => {
+                    guard.status[task] = Status::Succeeded;
+                    for i in &schedule.dependents[task] {
+                        guard.pending[*i] -= 1;
+                        if guard.pending[*i] == 0 {
+                            guard.ready.push_back(*i);
+                        }
+                    }
+                }
+                Err(e) => {
+                    guard.status[task] = Status::Failed;
+                    guard.errors.push((task, e));
+                    let mut stack = schedule.dependents[task].clone();
+                    while let Some(i) = stack.pop() {
+                        if guard.status[i] == Status::Pending {
+                            guard.status[i] = Status::Skipped;
+                            guard.finished += 1;
+                            stack.extend_from_slice(&schedule.dependents[i]);
+                        }
+                    }
+                }
+            }
+            info!("{} in progress: {}/{}", stage, guard.finished, total);
+            wakeup.notify_all();
+        }
+    };
+    let joined = crossbeam::scope(|s| {
+        for _ in 0..jobs.max(1).min(total.max(1)) {
+            s.spawn(|_| worker());
+        }
+    });
+    if let Err(e) = joined {
+        error!("a {} task panicked", stage);
+        // the scope collects the panics of all workers, only one of them can be resumed
+        match e.downcast::<Vec<Box<dyn Any + Send>>>() {
+            Ok(mut panics) if !panics.is_empty() => std::panic::resume_unwind(panics.remove(0)),
+            Ok(panics) => std::panic::resume_unwind(panics),
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    }
+    let state = state.into_inner().unwrap();
+    Outcome {
+        status: state.status,
+        errors: state.errors,
+        fatal: state.fatal,
+    }
+}
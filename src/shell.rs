@@ -0,0 +1,123 @@
+//! A lexer for the subset of POSIX shell found in build logs.
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Word(String),
+    /// `&&`, `||`, `;`, `|` or `&`
+    Operator(&'static str),
+}
+
+/// Split `line` into words and control operators, resolving quotes and escapes.
+pub fn lex(line: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    // a word may be empty but present, e.g. `''`
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(Error::Parse(format!("unterminated single quote in: {}", line)))
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ '"') | Some(c @ '\\') | Some(c @ '$') | Some(c @ '`') => word.push(c),
+                            Some('\n') => (),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(Error::Parse(format!("unterminated double quote in: {}", line)))
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(Error::Parse(format!("unterminated double quote in: {}", line)))
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => (),
+                Some(c) => {
+                    in_word = true;
+                    word.push(c);
+                }
+                None => return Err(Error::Parse(format!("trailing backslash in: {}", line)))
+            },
+            '&' | '|' | ';' => {
+                if in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+                let op = match (c, chars.peek()) {
+                    ('&', Some('&')) => "&&",
+                    ('|', Some('|')) => "||",
+                    ('&', _) => "&",
+                    ('|', _) => "|",
+                    _ => ";",
+                };
+                if op.len() == 2 {
+                    chars.next();
+                }
+                tokens.push(Token::Operator(op));
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
+
+/// Split `line` into words, failing on control operators.
+pub fn words(line: &str) -> Result<Vec<String>> {
+    lex(line)?.into_iter()
+        .map(|x| match x {
+            Token::Word(w) => Ok(w),
+            Token::Operator(op) => Err(Error::Parse(format!("unexpected `{}` in: {}", op, line)))
+        })
+        .collect()
+}
+
+/// Split `line` into simple commands at control operators.
+pub fn commands(line: &str) -> Result<Vec<Vec<String>>> {
+    let mut commands = vec![Vec::new()];
+    for token in lex(line)? {
+        match token {
+            Token::Word(w) => commands.last_mut().unwrap().push(w),
+            Token::Operator(_) => commands.push(Vec::new()),
+        }
+    }
+    commands.retain(|x| !x.is_empty());
+    Ok(commands)
+}
+
+/// Quote `word` so that `lex` reads it back unchanged.
+pub fn quote(word: &str) -> String {
+    if !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=+,:@%".contains(c)) {
+        String::from(word)
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
@@ -0,0 +1,267 @@
+//! Symbol tables of objects, archives and bitcode, and per-target resolution checks.
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+
+use goblin::archive::Archive;
+use goblin::elf::section_header::{SHN_ABS, SHN_COMMON, SHN_UNDEF};
+use goblin::elf::sym::{STB_GLOBAL, STB_GNU_UNIQUE, STB_LOCAL, STB_WEAK, STT_FILE, STT_SECTION};
+use goblin::elf::Elf;
+use hashbrown::{HashMap, HashSet};
+use log::*;
+use serde::*;
+
+use crate::cmaker::{Collection, Symbol};
+use crate::error::{Error, Result, Tool};
+use crate::scheduler::{execute, Schedule};
+use crate::targets::TargetGraph;
+use crate::Pipeline;
+
+const BITCODE: &[u8] = b"BC\xc0\xde";
+const BITCODE_WRAPPER: &[u8] = b"\xde\xc0\x17\x0b";
+
+/// Symbols of one object file, or of one archive member.
+#[derive(Debug, Default)]
+pub struct Table {
+    pub member: Option<String>,
+    pub defined: Vec<Symbol>,
+    pub undefined: Vec<Symbol>,
+}
+
+fn is_bitcode(bytes: &[u8]) -> bool {
+    bytes.starts_with(BITCODE) || bytes.starts_with(BITCODE_WRAPPER)
+}
+
+/// Read the symbol table of an ELF object or shared library, bitcode module or archive of those.
+pub fn read<P: AsRef<Path>>(path: P, llvm_nm: &str) -> Result<Vec<Table>> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)
+        .map_err(Error::io(format!("failed to read {}", path.display())))?;
+    let malformed = |e: goblin::error::Error| Error::Parse(format!("malformed {}: {}", path.display(), e));
+    if is_bitcode(&bytes) {
+        nm(path, llvm_nm)
+    } else if bytes.starts_with(b"\x7fELF") {
+        elf_table(&bytes).map(|x| vec![x]).map_err(malformed)
+    } else if bytes.starts_with(b"!<arch>\n") {
+        let archive = Archive::parse(&bytes).map_err(malformed)?;
+        let mut tables = Vec::new();
+        for member in archive.members() {
+            let content = archive.extract(member, &bytes).map_err(malformed)?;
+            if is_bitcode(content) {
+                return nm(path, llvm_nm);
+            }
+            if !content.starts_with(b"\x7fELF") {
+                continue;
+            }
+            let mut table = elf_table(content).map_err(malformed)?;
+            table.member = Some(String::from(member));
+            tables.push(table);
+        }
+        Ok(tables)
+    } else {
+        Err(Error::Parse(format!("{} is neither elf, bitcode nor an archive", path.display())))
+    }
+}
+
+fn elf_table(bytes: &[u8]) -> goblin::error::Result<Table> {
+    let elf = Elf::parse(bytes)?;
+    // shared libraries may be stripped down to their dynamic symbols
+    let (syms, strtab) = if elf.syms.is_empty() {
+        (&elf.dynsyms, &elf.dynstrtab)
+    } else {
+        (&elf.syms, &elf.strtab)
+    };
+    let mut table = Table::default();
+    for sym in syms.iter() {
+        let bind = sym.st_bind();
+        if bind == STB_LOCAL || sym.st_type() == STT_FILE || sym.st_type() == STT_SECTION {
+            continue;
+        }
+        let name = match strtab.get(sym.st_name) {
+            Some(Ok(name)) if !name.is_empty() => String::from(name),
+            _ => continue
+        };
+        let weak = bind == STB_WEAK || sym.st_shndx == SHN_COMMON as usize;
+        if sym.st_shndx == SHN_UNDEF as usize {
+            table.undefined.push(Symbol { name, weak });
+        } else if bind == STB_GLOBAL || bind == STB_WEAK || bind == STB_GNU_UNIQUE || sym.st_shndx == SHN_ABS as usize {
+            table.defined.push(Symbol { name, weak });
+        }
+    }
+    Ok(table)
+}
+
+/// Read bitcode symbols with `llvm-nm -P`, one table per archive member if `path` is an archive.
+fn nm(path: &Path, llvm_nm: &str) -> Result<Vec<Table>> {
+    let output = Command::new(llvm_nm)
+        .args(["-P", "--no-sort"])
+        .arg(path)
+        .output();
+    let output = match output {
+        Ok(x) if x.status.success() => x,
+        Ok(x) => return Error::tool(Tool::LlvmNm, Some(&path.to_string_lossy()), Ok(x.status)).map(|_| vec![]),
+        Err(e) => return Error::tool(Tool::LlvmNm, Some(&path.to_string_lossy()), Err(e)).map(|_| vec![]),
+    };
+    let mut tables = vec![Table::default()];
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        // archive members are headed by `member:` or `archive[member]:`
+        if line.ends_with(':') && !line.contains(char::is_whitespace) {
+            let name = line.trim_end_matches(':');
+            let member = match name.rfind('[') {
+                Some(x) => &name[x + 1..name.len() - 1],
+                None => name
+            };
+            tables.push(Table { member: Some(String::from(member)), ..Table::default() });
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (name, kind) = match (fields.next(), fields.next().and_then(|x| x.chars().next())) {
+            (Some(name), Some(kind)) => (String::from(name), kind),
+            _ => continue
+        };
+        let table = tables.last_mut().unwrap();
+        match kind {
+            'U' => table.undefined.push(Symbol { name, weak: false }),
+            'w' | 'v' => table.undefined.push(Symbol { name, weak: true }),
+            'W' | 'V' | 'C' => table.defined.push(Symbol { name, weak: true }),
+            c if c.is_ascii_uppercase() => table.defined.push(Symbol { name, weak: false }),
+            _ => ()
+        }
+    }
+    tables.retain(|x| x.member.is_some() || !x.defined.is_empty() || !x.undefined.is_empty());
+    Ok(tables)
+}
+
+/// Fill in the symbols of every object in `collection` that exists on disk, reading at most
+/// `jobs` of them at once. Objects that cannot be read are warned about, a failing `llvm-nm` fails.
+pub fn fill(pipeline: &Pipeline, collection: &mut Collection) -> Result<()> {
+    let llvm_nm = pipeline.config().llvm_nm();
+    let objects = collection.objects.iter_mut().map(Mutex::new).collect::<Vec<_>>();
+    execute("reading symbols", pipeline.jobs(), &Schedule::new(objects.len()), |i| {
+        let mut x = objects[i].lock().unwrap();
+        match read(&x.abs_path, llvm_nm) {
+            Ok(tables) => for table in tables {
+                x.defined_symbols.extend(table.defined);
+                x.undefined_symbols.extend(table.undefined);
+            },
+            Err(e @ Error::Tool { .. }) => return Err(e),
+            Err(e) => warn!("cannot read symbols of {}: {}", x.abs_path, e)
+        }
+        Ok(())
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Duplicate {
+    pub symbol: String,
+    pub definitions: Vec<String>,
+}
+
+/// How the objects merged into one linked module resolve each other.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SymbolReport {
+    pub target: String,
+    pub unresolved: Vec<String>,
+    pub duplicates: Vec<Duplicate>,
+}
+
+/// Objects and external libraries ending up in target `i`, following linked targets.
+fn closure<'a>(graph: &TargetGraph<'a>, i: usize) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut objects = Vec::new();
+    let mut external = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![i];
+    while let Some(t) = stack.pop() {
+        if !visited.insert(t) {
+            continue;
+        }
+        external.extend(graph.script(t).target.dependencies.iter()
+            .map(String::as_str)
+            .filter(|x| !graph.is_object(x) && !graph.is_target(x)));
+        for dep in graph.inputs(t) {
+            match graph.index_of(dep) {
+                Some(j) => stack.push(j),
+                None => objects.push(dep),
+            }
+        }
+    }
+    objects.sort_unstable();
+    objects.dedup();
+    external.sort_unstable();
+    external.dedup();
+    (objects, external)
+}
+
+/// Check every target for unresolved symbols and duplicate strong definitions.
+///
+/// External archives and shared libraries named as dependencies count as definitions only.
+pub fn check(pipeline: &Pipeline, collection: &Collection, graph: &TargetGraph) -> Result<Vec<SymbolReport>> {
+    let llvm_nm = pipeline.config().llvm_nm();
+    let objects = collection.objects.iter()
+        .map(|x| (x.abs_path.as_str(), x))
+        .collect::<HashMap<_, _>>();
+    let reports = (0..graph.len()).map(|_| Mutex::new(SymbolReport::default())).collect::<Vec<_>>();
+    execute("checking symbols", pipeline.jobs(), &Schedule::new(reports.len()), |i| {
+        let (inputs, external) = closure(graph, i);
+        let mut strong: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut defined = HashSet::new();
+        for x in &inputs {
+            for s in &objects[x].defined_symbols {
+                defined.insert(s.name.clone());
+                if !s.weak {
+                    strong.entry(s.name.as_str()).or_default().push(x);
+                }
+            }
+        }
+        for x in &external {
+            match read(x, llvm_nm) {
+                Ok(tables) => defined.extend(tables.into_iter().flat_map(|t| t.defined).map(|s| s.name)),
+                Err(e @ Error::Tool { .. }) => return Err(e),
+                Err(e) => debug!("cannot read symbols of {}: {}", x, e)
+            }
+        }
+        let mut unresolved = inputs.iter()
+            .flat_map(|x| objects[x].undefined_symbols.iter())
+            .filter(|s| !s.weak && !defined.contains(&s.name))
+            .map(|s| s.name.clone())
+            .collect::<Vec<_>>();
+        unresolved.sort_unstable();
+        unresolved.dedup();
+        let mut duplicates = strong.into_iter()
+            .filter(|(_, x)| x.len() > 1)
+            .map(|(symbol, definitions)| Duplicate {
+                symbol: String::from(symbol),
+                definitions: definitions.into_iter().map(String::from).collect(),
+            })
+            .collect::<Vec<_>>();
+        duplicates.sort_unstable_by(|a, b| a.symbol.cmp(&b.symbol));
+        *reports[i].lock().unwrap() = SymbolReport {
+            target: graph.script(i).target.abs_path.clone(),
+            unresolved,
+            duplicates,
+        };
+        Ok(())
+    })?;
+    let reports = reports.into_iter().map(|x| x.into_inner().unwrap()).collect::<Vec<_>>();
+
+    let path = pipeline.build_dir().join("symbol_report.json");
+    serde_json::to_string_pretty(&reports)
+        .map_err(|e| Error::Parse(format!("failed to serialize symbol report: {}", e)))
+        .and_then(|x| std::fs::write(&path, x)
+            .map_err(Error::io(format!("failed to store {}", path.display()))))?;
+    info!("symbol report saved at {:?}", path);
+    for report in &reports {
+        if !report.unresolved.is_empty() {
+            info!("{}: {} unresolved symbol(s)", report.target, report.unresolved.len());
+        }
+        for dup in &report.duplicates {
+            warn!("{}: {} defined in {}", report.target, dup.symbol, dup.definitions.join(", "));
+        }
+    }
+    let duplicated = reports.iter().filter(|x| !x.duplicates.is_empty()).count();
+    if duplicated > 0 && pipeline.config().strict_symbols {
+        return Err(Error::Graph(format!("{} target(s) have duplicate symbol definitions", duplicated)));
+    }
+    Ok(reports)
+}
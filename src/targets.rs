@@ -0,0 +1,283 @@
+use hashbrown::{HashMap, HashSet};
+use log::*;
+
+use crate::cmaker::{Collection, LinkScript, Object, TargetKind};
+use crate::error::{Error, Result};
+use crate::scheduler::Schedule;
+
+/// Problems found in a target graph before linking.
+#[derive(Debug, Default)]
+pub struct Analysis<'a> {
+    /// Each cycle as the chain of targets, starting and ending with the same one.
+    pub cycles: Vec<Vec<usize>>,
+    /// Dependencies that name neither a known object nor a target.
+    pub unknown: Vec<(usize, &'a str)>,
+    /// Targets outside any cycle that depend on one and can never become ready.
+    pub blocked: Vec<usize>,
+}
+
+/// Dependency graph between the link scripts of a collection.
+///
+/// Nodes are indices into `Collection::scripts`; an edge `i -> j` means target `i`
+/// links target `j`.
+pub struct TargetGraph<'a> {
+    collection: &'a Collection,
+    index: HashMap<&'a str, usize>,
+    objects: HashMap<&'a str, &'a Object>,
+    dependencies: Vec<Vec<usize>>,
+}
+
+impl<'a> TargetGraph<'a> {
+    pub fn new(collection: &'a Collection) -> Self {
+        let index = collection.scripts.iter().enumerate()
+            .map(|(i, x)| (x.target.abs_path.as_str(), i))
+            .collect::<HashMap<_, _>>();
+        let objects = collection.objects.iter().map(|x| (x.abs_path.as_str(), x)).collect();
+        let dependencies = collection.scripts.iter()
+            .map(|x| {
+                let mut deps = x.target.dependencies.iter()
+                    .filter_map(|d| index.get(d.as_str()).copied())
+                    .collect::<Vec<_>>();
+                deps.sort_unstable();
+                deps.dedup();
+                deps
+            })
+            .collect();
+        TargetGraph {
+            collection,
+            index,
+            objects,
+            dependencies,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dependencies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dependencies.is_empty()
+    }
+
+    pub fn script(&self, i: usize) -> &'a LinkScript {
+        &self.collection.scripts[i]
+    }
+
+    pub fn index_of(&self, target: &str) -> Option<usize> {
+        self.index.get(target).copied()
+    }
+
+    pub fn is_target(&self, path: &str) -> bool {
+        self.index.contains_key(path)
+    }
+
+    pub fn is_object(&self, path: &str) -> bool {
+        self.objects.contains_key(path)
+    }
+
+    pub fn object(&self, path: &str) -> Option<&'a Object> {
+        self.objects.get(path).copied()
+    }
+
+    /// Targets linked by target `i`.
+    pub fn dependencies(&self, i: usize) -> &[usize] {
+        &self.dependencies[i]
+    }
+
+    /// Objects and targets linked into target `i`, sorted and deduplicated.
+    ///
+    /// Static libraries linked by an executable or shared library contribute only the members
+    /// needed to resolve undefined references, as if all of them were in one linker group.
+    /// Libraries whose members carry no symbols are linked whole.
+    pub fn inputs(&self, i: usize) -> Vec<&'a str> {
+        let target = &self.script(i).target;
+        let mut inputs = Vec::new();
+        let mut archives = Vec::new();
+        for dep in &target.dependencies {
+            match self.index_of(dep) {
+                Some(j) if target.kind.is_linked() && self.script(j).target.kind == TargetKind::StaticLibrary
+                    && self.members(j).any(|x| !x.defined_symbols.is_empty()) => archives.push(j),
+                Some(_) => inputs.push(dep.as_str()),
+                None if self.is_object(dep) => inputs.push(dep.as_str()),
+                None => ()
+            }
+        }
+
+        let mut defined = HashSet::new();
+        let mut undefined = HashSet::new();
+        for x in &inputs {
+            match self.object(x) {
+                Some(object) => {
+                    defined.extend(object.defined_symbols.iter().map(|s| s.name.as_str()));
+                    undefined.extend(object.undefined_symbols.iter().filter(|s| !s.weak).map(|s| s.name.as_str()));
+                }
+                None => self.collect_defined(self.index_of(x).unwrap(), &mut HashSet::new(), &mut defined)
+            }
+        }
+        let mut included = HashSet::new();
+        loop {
+            undefined.retain(|x| !defined.contains(x));
+            let member = archives.iter()
+                .flat_map(|j| self.members(*j))
+                .find(|x| !included.contains(x.abs_path.as_str())
+                    && x.defined_symbols.iter().any(|s| undefined.contains(s.name.as_str())));
+            let member = match member {
+                Some(member) => member,
+                None => break
+            };
+            trace!("{} pulls {} from its static libraries", target.abs_path, member.abs_path);
+            included.insert(member.abs_path.as_str());
+            inputs.push(member.abs_path.as_str());
+            defined.extend(member.defined_symbols.iter().map(|s| s.name.as_str()));
+            undefined.extend(member.undefined_symbols.iter().filter(|s| !s.weak).map(|s| s.name.as_str()));
+        }
+        inputs.sort_unstable();
+        inputs.dedup();
+        inputs
+    }
+
+    /// Objects archived into target `i`.
+    fn members(&self, i: usize) -> impl Iterator<Item=&'a Object> + '_ {
+        self.script(i).target.dependencies.iter().filter_map(move |x| self.object(x))
+    }
+
+    fn collect_defined(&self, i: usize, visited: &mut HashSet<usize>, defined: &mut HashSet<&'a str>) {
+        if !visited.insert(i) {
+            return;
+        }
+        for dep in &self.script(i).target.dependencies {
+            match (self.object(dep), self.index_of(dep)) {
+                (Some(object), _) => defined.extend(object.defined_symbols.iter().map(|s| s.name.as_str())),
+                (None, Some(j)) => self.collect_defined(j, visited, defined),
+                (None, None) => ()
+            }
+        }
+    }
+
+    /// A schedule running every target after the targets it links.
+    pub fn schedule(&self) -> Schedule {
+        let mut schedule = Schedule::new(self.len());
+        for (i, deps) in self.dependencies.iter().enumerate() {
+            for j in deps {
+                schedule.add_dependency(i, *j);
+            }
+        }
+        schedule
+    }
+
+    /// Render the graph in Graphviz DOT.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph targets {\n");
+        for i in 0..self.len() {
+            dot += &format!("    {} [label={:?}];\n", i, self.script(i).target.name);
+        }
+        for (i, deps) in self.dependencies.iter().enumerate() {
+            for j in deps {
+                dot += &format!("    {} -> {};\n", i, j);
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+
+    /// Find cycles, unknown dependencies and targets that can never become ready.
+    pub fn analyze(&self) -> Analysis<'a> {
+        let mut analysis = Analysis::default();
+        for i in 0..self.len() {
+            for dep in &self.script(i).target.dependencies {
+                if !self.is_target(dep) && !self.is_object(dep) {
+                    analysis.unknown.push((i, dep.as_str()));
+                }
+            }
+        }
+
+        // 0: unvisited, 1: on the current path, 2: done
+        let mut color = vec![0u8; self.len()];
+        let mut in_cycle = vec![false; self.len()];
+        for root in 0..self.len() {
+            if color[root] != 0 {
+                continue;
+            }
+            let mut path = vec![(root, 0)];
+            color[root] = 1;
+            while let Some((node, next)) = path.last_mut() {
+                let node = *node;
+                match self.dependencies[node].get(*next) {
+                    Some(&dep) => {
+                        *next += 1;
+                        match color[dep] {
+                            0 => {
+                                color[dep] = 1;
+                                path.push((dep, 0));
+                            }
+                            1 => {
+                                let start = path.iter().position(|x| x.0 == dep).unwrap();
+                                let mut cycle = path[start..].iter().map(|x| x.0).collect::<Vec<_>>();
+                                cycle.push(dep);
+                                for i in &cycle {
+                                    in_cycle[*i] = true;
+                                }
+                                analysis.cycles.push(cycle);
+                            }
+                            _ => ()
+                        }
+                    }
+                    None => {
+                        color[node] = 2;
+                        path.pop();
+                    }
+                }
+            }
+        }
+
+        let mut dependents = vec![Vec::new(); self.len()];
+        for (i, deps) in self.dependencies.iter().enumerate() {
+            for j in deps {
+                dependents[*j].push(i);
+            }
+        }
+        let mut blocked = in_cycle.clone();
+        let mut stack = (0..self.len()).filter(|x| in_cycle[*x]).collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            for i in &dependents[node] {
+                if !blocked[*i] {
+                    blocked[*i] = true;
+                    analysis.blocked.push(*i);
+                    stack.push(*i);
+                }
+            }
+        }
+        analysis.blocked.sort_unstable();
+        analysis
+    }
+
+    /// Fail if any target could never be linked; unknown dependencies fail only when `strict`.
+    pub fn check(&self, strict: bool) -> Result<()> {
+        let analysis = self.analyze();
+        let mut problems = Vec::new();
+        for (i, dep) in &analysis.unknown {
+            let message = format!("target {} depends on unknown input {}", self.script(*i).target.name, dep);
+            if strict {
+                problems.push(message);
+            } else {
+                warn!("{}", message);
+            }
+        }
+        for cycle in &analysis.cycles {
+            let chain = cycle.iter()
+                .map(|x| self.script(*x).target.name.as_str())
+                .collect::<Vec<_>>();
+            problems.push(format!("dependency cycle: {}", chain.join(" -> ")));
+        }
+        for i in &analysis.blocked {
+            problems.push(format!("target {} can never become ready, it depends on a cycle", self.script(*i).target.name));
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+        for p in &problems {
+            error!("{}", p);
+        }
+        Err(Error::Graph(problems.join("; ")))
+    }
+}
@@ -0,0 +1,161 @@
+//! Read objects, link scripts and compile commands from the `remake -x` trace.
+
+use std::path::{Path, PathBuf};
+
+use hashbrown::HashSet;
+use log::*;
+
+use crate::cmaker::{self, Collection, LinkScript, Object, Target, TargetKind};
+use crate::command::CompileCommand;
+use crate::error::{Error, Result};
+use crate::shell;
+use crate::symbols;
+use crate::Pipeline;
+
+pub(crate) fn file_name(path: &str) -> String {
+    Path::new(path).file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from(path))
+}
+
+pub(crate) fn absolute(cwd: &Path, path: &str) -> String {
+    cwd.join(path).to_string_lossy().into_owned()
+}
+
+/// Recognise an archiver or a linking compiler invocation run in `cwd`.
+pub(crate) fn link_target(cwd: &Path, words: Vec<String>, compilers: &[&str]) -> Result<Option<Target>> {
+    let program = file_name(&words[0]);
+    if (program == "ar" || program.ends_with("-ar")) && words.len() > 2
+        && (words[1].contains('q') || words[1].contains('r')) {
+        Ok(Some(Target {
+            name: file_name(&words[2]),
+            abs_path: absolute(cwd, &words[2]),
+            dependencies: words[3..].iter().map(|x| absolute(cwd, x)).collect(),
+            kind: TargetKind::StaticLibrary,
+        }))
+    } else if compilers.contains(&words[0].as_str()) && !words.iter().any(|x| x == "-c") {
+        let shared = words.iter().any(|x| x == "-shared");
+        let command = CompileCommand::from_words(Some(PathBuf::from(cwd)), words)?;
+        Ok(Some(Target {
+            name: file_name(&command.output),
+            abs_path: absolute(cwd, &command.output),
+            dependencies: command.inputs.iter().map(|x| absolute(cwd, x)).collect(),
+            kind: if shared { TargetKind::SharedLibrary } else { TargetKind::Executable },
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The directory in a make `Entering directory '...'` message.
+fn entered_directory(line: &str) -> Option<&str> {
+    let rest = &line[line.find("Entering directory")? + "Entering directory".len()..];
+    let rest = rest.trim().trim_start_matches(['\'', '`']);
+    Some(rest.trim_end_matches('\''))
+}
+
+/// Parse a remake trace run in `build_dir`.
+///
+/// Lines that do not lex as shell commands (make's own messages) are skipped. Link steps run
+/// through `cmake -E cmake_link_script` are read from the named script.
+pub fn parse(log: &str, build_dir: &Path, compilers: &[&str]) -> Result<Collection> {
+    let mut dir = PathBuf::from(build_dir);
+    let mut compile = Vec::new();
+    let mut objects = Vec::new();
+    let mut scripts = Vec::new();
+    let mut seen = HashSet::new();
+    for line in log.lines() {
+        if let Some(entered) = entered_directory(line) {
+            dir = PathBuf::from(entered);
+            continue;
+        }
+        let commands = match shell::commands(line) {
+            Ok(commands) => commands,
+            Err(_) => continue
+        };
+        // the compile stage resolves the object against the line's own `cd`, which has to lead
+        // to the same directory
+        let leaves = commands.first()
+            .map(|x| x[0] == "cd" && x.get(1).map(|x| Path::new(x).is_absolute()).unwrap_or(false))
+            .unwrap_or(false);
+        let mut cwd = dir.clone();
+        for words in commands {
+            if words[0] == "cd" && words.len() > 1 {
+                cwd = cwd.join(&words[1]);
+            } else if compilers.contains(&words[0].as_str()) && words.iter().any(|x| x == "-c") {
+                let command = CompileCommand::from_words(Some(cwd.clone()), words)?;
+                let abs_path = command.object();
+                if !seen.insert(abs_path.clone()) {
+                    break;
+                }
+                let line = if leaves {
+                    String::from(line.trim())
+                } else {
+                    format!("cd {} && {}", shell::quote(&dir.to_string_lossy()), line.trim())
+                };
+                trace!("found object {}", abs_path);
+                objects.push(Object {
+                    name: file_name(&command.output),
+                    abs_path,
+                    defined_symbols: Vec::new(),
+                    undefined_symbols: Vec::new(),
+                });
+                compile.push(line);
+                break;
+            } else if file_name(&words[0]) == "cmake" && words.get(2).map(|x| x == "cmake_link_script").unwrap_or(false) {
+                let script = cwd.join(words.get(3)
+                    .ok_or_else(|| Error::Parse(format!("missing link script in: {}", line)))?);
+                for target in read_link_script(&script, &cwd, compilers)? {
+                    if seen.insert(target.abs_path.clone()) {
+                        trace!("found target {} linking {:?}", target.abs_path, target.dependencies);
+                        scripts.push(LinkScript {
+                            abs_path: script.to_string_lossy().into_owned(),
+                            target,
+                        });
+                    }
+                }
+                break;
+            } else if let Some(target) = link_target(&cwd, words, compilers)? {
+                if seen.insert(target.abs_path.clone()) {
+                    trace!("found target {} linking {:?}", target.abs_path, target.dependencies);
+                    scripts.push(LinkScript {
+                        abs_path: target.abs_path.clone(),
+                        target,
+                    });
+                }
+                break;
+            }
+        }
+    }
+    Ok(Collection {
+        objects,
+        scripts,
+        compile,
+    })
+}
+
+fn read_link_script(script: &Path, cwd: &Path, compilers: &[&str]) -> Result<Vec<Target>> {
+    let content = std::fs::read_to_string(script)
+        .map_err(Error::io(format!("failed to read link script {}", script.display())))?;
+    let mut targets = Vec::new();
+    for line in content.lines() {
+        for words in shell::commands(line)? {
+            targets.extend(link_target(cwd, words, compilers)?);
+        }
+    }
+    Ok(targets)
+}
+
+/// Parse `rz_build/remake.log` and store the result as `cmaker.log`.
+pub fn extract(pipeline: &Pipeline) -> Result<()> {
+    let config = pipeline.config();
+    let build_dir = pipeline.build_dir();
+    let log = build_dir.join("remake.log");
+    let content = std::fs::read_to_string(&log)
+        .map_err(Error::io(format!("failed to read {}", log.display())))?;
+    let mut collection = parse(&content, &build_dir,
+                               &[config.original_cxx_executable.as_str(), config.original_cc_executable.as_str()])?;
+    info!("found {} object(s) and {} target(s) in remake log", collection.objects.len(), collection.scripts.len());
+    symbols::fill(pipeline, &mut collection)?;
+    cmaker::store_collection(&collection, build_dir.join("cmaker.log"))
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use rzlinkhelper_rs::cache::{Cache, Job};
+
+#[test]
+fn settings_are_part_of_the_key() {
+    let dir = std::env::temp_dir().join(format!("rzlinkhelper-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = dir.join("graph");
+    std::fs::write(&output, "[]").unwrap();
+    let job = |demangle: bool| Job {
+        output: &output,
+        tool: "/nonexistent/opt",
+        command: vec![String::from("opt"), String::from("module.bc")],
+        settings: vec![format!("demangle={}", demangle)],
+        inputs: Vec::<PathBuf>::new(),
+    };
+    let cache = Cache::load(dir.join("cache.json")).unwrap();
+    cache.record(&job(false));
+    assert!(cache.is_fresh(&job(false)));
+    assert!(!cache.is_fresh(&job(true)));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
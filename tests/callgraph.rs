@@ -0,0 +1,145 @@
+use rzlinkhelper_rs::callgraph::{is_graph_line, parse, Call, Callee, ErrorKind, Node, ParseError};
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/callgraph/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e))
+}
+
+fn function(name: &str) -> Callee {
+    Callee::Function(String::from(name))
+}
+
+fn call(site: &str, name: &str) -> Call {
+    Call { site: Some(String::from(site)), callee: function(name) }
+}
+
+/// The callees of `node`, in call site order.
+fn callees(node: &Node) -> Vec<Callee> {
+    node.calls.iter().map(|x| x.callee.clone()).collect()
+}
+
+fn error(name: &str) -> ParseError {
+    parse(&fixture(name)).expect_err("fixture should not parse")
+}
+
+#[test]
+fn basic() {
+    let nodes = parse(&fixture("basic.txt")).unwrap();
+    assert_eq!(nodes, vec![
+        Node {
+            function: Some(String::from("main")),
+            address: Some(0x55d0c8a0a640),
+            uses: 1,
+            calls: vec![call("0x55d0c8a0b9d8", "foo"), call("0x55d0c8a0ba88", "bar")],
+        },
+        Node {
+            function: Some(String::from("foo")),
+            address: Some(0x55d0c8a0a6f0),
+            uses: 2,
+            calls: vec![call("0x55d0c8a0bb38", "bar")],
+        },
+        Node {
+            function: Some(String::from("bar")),
+            address: Some(0x55d0c8a0a7a0),
+            uses: 3,
+            calls: vec![],
+        },
+    ]);
+}
+
+#[test]
+fn null_function_and_external_node() {
+    let nodes = parse(&fixture("external.txt")).unwrap();
+    assert_eq!(nodes.len(), 4);
+    assert_eq!(nodes[0].function, None);
+    assert_eq!(nodes[0].address, Some(0x1e4b0b0));
+    assert_eq!(callees(&nodes[0]), vec![function("main"), function("callback")]);
+    assert!(nodes[0].calls.iter().all(|x| x.site.is_none()));
+    assert_eq!(callees(&nodes[1]), vec![function("printf"), Callee::External]);
+    assert_eq!(nodes[2].calls, vec![Call { site: None, callee: Callee::External }]);
+}
+
+#[test]
+fn indirect_calls_with_instruction_text() {
+    let nodes = parse(&fixture("indirect.txt")).unwrap();
+    assert_eq!(nodes[1].function.as_deref(), Some("apply"));
+    assert_eq!(nodes[1].calls, vec![Call {
+        site: Some(String::from("%3 = call i32 %0(i32 %1), !dbg !17")),
+        callee: Callee::External,
+    }]);
+    assert_eq!(callees(&nodes[2]), vec![function("apply"), function("llvm.dbg.declare")]);
+}
+
+#[test]
+fn demangled_names() {
+    let nodes = parse(&fixture("demangled.txt")).unwrap();
+    assert_eq!(callees(&nodes[0]), vec![
+        function("std::vector<int, std::allocator<int> >::push_back(int const&)"),
+        function("operator new(unsigned long)"),
+        function("ns::f(char const*, ...)"),
+        function("(anonymous namespace)::quote('a')"),
+        function("std::operator<<(std::ostream&, char const*)"),
+    ]);
+    let names = nodes.iter().filter_map(|x| x.function.as_deref()).collect::<Vec<_>>();
+    assert_eq!(names, vec![
+        "main",
+        "std::vector<int, std::allocator<int> >::push_back(int const&)",
+        "(anonymous namespace)::quote('a')",
+        "std::operator<<(std::ostream&, char const*)",
+    ]);
+    assert_eq!(nodes[3].address, Some(0x7f70));
+}
+
+#[test]
+fn missing_address_and_leading_banner() {
+    let nodes = parse(&fixture("no_address.txt")).unwrap();
+    assert_eq!(nodes.len(), 3);
+    assert_eq!(nodes[0].function.as_deref(), Some("main"));
+    assert_eq!(nodes[0].address, None);
+    assert_eq!(callees(&nodes[0]), vec![function("foo")]);
+    assert_eq!(nodes[1].function, None);
+    assert_eq!(nodes[2].uses, 2);
+}
+
+#[test]
+fn bad_uses() {
+    assert_eq!(error("bad_uses.txt"), ParseError { line: 3, kind: ErrorKind::BadUses(String::from("many")) });
+}
+
+#[test]
+fn missing_uses() {
+    assert_eq!(error("missing_uses.txt"), ParseError { line: 1, kind: ErrorKind::MissingUses });
+}
+
+#[test]
+fn bad_address() {
+    assert_eq!(error("bad_address.txt"), ParseError { line: 1, kind: ErrorKind::BadAddress(String::from("0xzz")) });
+}
+
+#[test]
+fn call_outside_node() {
+    assert_eq!(error("call_outside_node.txt"), ParseError { line: 2, kind: ErrorKind::CallOutsideNode });
+}
+
+#[test]
+fn unterminated_name() {
+    assert_eq!(error("unterminated_name.txt"), ParseError { line: 2, kind: ErrorKind::UnterminatedName });
+}
+
+#[test]
+fn unexpected_line() {
+    let e = error("unexpected_line.txt");
+    assert_eq!(e, ParseError { line: 3, kind: ErrorKind::UnexpectedLine(String::from("something else")) });
+    assert_eq!(e.to_string(), "line 3: unexpected line: something else");
+}
+
+#[test]
+fn diagnostics_are_told_apart() {
+    let text = fixture("diagnostics.txt");
+    let (graph, messages): (Vec<&str>, Vec<&str>) = text.lines().partition(|x| is_graph_line(x));
+    assert_eq!(messages, vec![
+        "warning: ignoring debug info with an invalid version (0) in a.bc",
+        "opt: a.bc: warning: overriding the module target triple",
+    ]);
+    assert_eq!(parse(&graph.join("\n")).unwrap(), parse(&fixture("basic.txt")).unwrap());
+}
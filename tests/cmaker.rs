@@ -0,0 +1,39 @@
+use std::os::unix::fs::PermissionsExt;
+
+use serde_json::json;
+
+use rzlinkhelper_rs::{Configuration, Pipeline};
+
+/// The sample config from the README with `cmake` replaced by a script recording its arguments.
+fn config(dir: &std::path::Path, build_backend: &str) -> Configuration {
+    let readme = std::fs::read_to_string(format!("{}/README.md", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let start = readme.find("```json5\n").unwrap() + "```json5\n".len();
+    let end = start + readme[start..].find("```").unwrap();
+    let mut config: serde_json::Value = serde_json::from_str(&readme[start..end]).unwrap();
+    let cmake = dir.join("cmake");
+    std::fs::write(&cmake, "#!/bin/sh\necho \"$@\" > args\n").unwrap();
+    std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    config["cmake_executable"] = json!(cmake);
+    config["ninja_executable"] = json!("/opt/ninja/bin/ninja");
+    config["build_backend"] = json!(build_backend);
+    serde_json::from_value(config).unwrap()
+}
+
+/// The arguments cmake is run with under `build_backend`.
+fn cmake_args(build_backend: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("rzlinkhelper-cmaker-{}-{}", std::process::id(), build_backend));
+    std::fs::create_dir_all(&dir).unwrap();
+    let pipeline = Pipeline::new(config(&dir, build_backend), &dir).unwrap();
+    pipeline.run_cmake().unwrap();
+    let args = std::fs::read_to_string(pipeline.build_dir().join("args")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    args
+}
+
+#[test]
+fn ninja_executable_is_only_passed_to_ninja() {
+    let make = cmake_args("make");
+    assert!(!make.contains("CMAKE_MAKE_PROGRAM"), "{}", make);
+    let ninja = cmake_args("ninja");
+    assert!(ninja.contains("-G Ninja -DCMAKE_MAKE_PROGRAM=/opt/ninja/bin/ninja"), "{}", ninja);
+}
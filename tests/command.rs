@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+
+use rzlinkhelper_rs::command::CompileCommand;
+use rzlinkhelper_rs::compdb::Entry;
+
+const COMPILERS: &[&str] = &["/usr/bin/c++", "/usr/bin/cc"];
+
+fn fixtures(dir: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(dir)
+}
+
+fn fixture(dir: &str, name: &str) -> String {
+    let text = std::fs::read_to_string(fixtures(dir).join(name)).unwrap();
+    String::from(text.trim_end())
+}
+
+#[test]
+fn spaces_in_paths() {
+    let command = CompileCommand::parse(&fixture("shell", "spaces.txt"), COMPILERS).unwrap();
+    assert_eq!(command.cwd, Some(PathBuf::from("/home/u/my project/build")));
+    assert_eq!(command.compiler, "/usr/bin/c++");
+    assert_eq!(command.output, "CMakeFiles/x.dir/a b.cpp.o");
+    assert_eq!(command.inputs, vec!["../src/a b.cpp"]);
+    assert_eq!(command.args, vec!["-I/opt/with space/include", "-c", "../src/a b.cpp"]);
+    assert_eq!(command.input_paths(Path::new("/")), vec![PathBuf::from("/home/u/my project/build/../src/a b.cpp")]);
+}
+
+#[test]
+fn quoted_defines_are_not_inputs() {
+    let command = CompileCommand::parse(&fixture("shell", "defines.txt"), COMPILERS).unwrap();
+    assert_eq!(command.cwd, None);
+    assert_eq!(command.output, "a.o");
+    assert_eq!(command.args[..4], ["-DNAME=\"value\"", "-DGREETING=\"hello world\"", "-DPATH=\"/a b\"", "-DEMPTY="]);
+    assert_eq!(command.inputs, vec!["a.c"]);
+}
+
+#[test]
+fn first_compiler_in_a_chain() {
+    let command = CompileCommand::parse(&fixture("shell", "chain.txt"), COMPILERS).unwrap();
+    assert_eq!(command.cwd, Some(PathBuf::from("/b")));
+    assert_eq!(command.args, vec!["-c", "a.cpp"]);
+    assert_eq!(command.output, "a.o");
+    assert!(CompileCommand::parse("cd /b && make all", COMPILERS).is_err());
+}
+
+#[test]
+fn separate_option_values() {
+    let words = "cc -I include -D NAME -x c -isystem /sys -c a.c -o a.o".split(' ').map(String::from).collect();
+    let command = CompileCommand::from_words(None, words).unwrap();
+    assert_eq!(command.inputs, vec!["a.c"]);
+    assert_eq!(command.output, "a.o");
+    let words = vec![String::from("cc"), String::from("-c"), String::from("a.c"), String::from("-oa.o")];
+    assert_eq!(CompileCommand::from_words(None, words).unwrap().output, "a.o");
+    let words = vec![String::from("cc"), String::from("-c"), String::from("a.c")];
+    assert!(CompileCommand::from_words(None, words).is_err());
+}
+
+#[test]
+fn response_files() {
+    let line = format!("cd {} && {}", fixtures("command").display(), fixture("command", "response.txt"));
+    let command = CompileCommand::parse(&line, COMPILERS).unwrap();
+    assert_eq!(command.args, vec!["-DFOO=1", "-I/with space/include", "-O2", "-c", "a.cpp"]);
+    assert_eq!(command.output, "out/a.o");
+    assert_eq!(command.inputs, vec!["a.cpp"]);
+}
+
+#[test]
+fn recursive_response_file() {
+    let words = vec![String::from("cc"), String::from("@self.rsp"), String::from("-c"), String::from("a.c")];
+    assert!(CompileCommand::from_words(Some(fixtures("command")), words).is_err());
+    let words = vec![String::from("cc"), String::from("@missing.rsp")];
+    assert!(CompileCommand::from_words(Some(fixtures("command")), words).is_err());
+}
+
+#[test]
+fn objects_resolve_against_the_directory() {
+    let entry = Entry {
+        directory: String::from("/build/my app"),
+        file: String::from("/src/a.cpp"),
+        command: Some(String::from("/usr/bin/c++ -c /src/a.cpp -o CMakeFiles/app.dir/a.cpp.o")),
+        arguments: None,
+        output: None,
+    };
+    let command = CompileCommand::parse(&entry.to_compile().unwrap(), COMPILERS).unwrap();
+    assert_eq!(command.output, "CMakeFiles/app.dir/a.cpp.o");
+    assert_eq!(command.object(), "/build/my app/CMakeFiles/app.dir/a.cpp.o");
+    let words = vec![String::from("cc"), String::from("-c"), String::from("a.c"), String::from("-o"), String::from("a.o")];
+    assert_eq!(CompileCommand::from_words(None, words).unwrap().object(), "a.o");
+}
+
+#[test]
+fn database_entries_of_other_tools_are_told_apart() {
+    let entry = |command: &str| Entry {
+        directory: String::from("/build"),
+        file: String::from("/src/a.c"),
+        command: Some(String::from(command)),
+        arguments: None,
+        output: None,
+    };
+    assert!(entry("/usr/bin/cc -c /src/a.c -o a.o").is_compiled_by(COMPILERS).unwrap());
+    assert!(!entry("ccache /usr/bin/cc -c /src/a.c -o a.o").is_compiled_by(COMPILERS).unwrap());
+    assert!(!entry("/usr/bin/as /src/start.s -o start.o").is_compiled_by(COMPILERS).unwrap());
+    assert!(!entry("/usr/bin/cc a.o -o app").is_compiled_by(COMPILERS).unwrap());
+}
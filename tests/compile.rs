@@ -0,0 +1,26 @@
+use std::path::PathBuf;
+
+use rzlinkhelper_rs::command::CompileCommand;
+use rzlinkhelper_rs::compile::depfile_inputs;
+
+#[test]
+fn depfile_lists_sources_and_headers() {
+    let text = std::fs::read_to_string(format!("{}/tests/fixtures/compile/main.d", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    assert_eq!(depfile_inputs(&text), vec![
+        "../src/main.cpp",
+        "../include/app.h",
+        "/usr/include/stdio.h",
+        "../include/with space.h",
+        "../include/cost$.h",
+    ]);
+}
+
+#[test]
+fn bitcode_command_writes_its_own_depfile() {
+    let words = "c++ -MD -MT CMakeFiles/app.dir/main.cpp.o -MF CMakeFiles/app.dir/main.cpp.o.d -O2 -c ../src/main.cpp -o CMakeFiles/app.dir/main.cpp.o"
+        .split(' ').map(String::from).collect();
+    let command = CompileCommand::from_words(Some(PathBuf::from("/build")), words).unwrap();
+    assert_eq!(command.to_llvm("clang++", "/objects/main.o"), vec![
+        "clang++", "-emit-llvm", "-O2", "-c", "../src/main.cpp", "-MD", "-MF", "/objects/main.o.d", "-o", "/objects/main.o",
+    ]);
+}
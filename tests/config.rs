@@ -0,0 +1,70 @@
+use serde_json::{json, Value};
+
+use rzlinkhelper_rs::config::{BuildBackend, CompilePolicy, GraphBackend};
+use rzlinkhelper_rs::export::Format;
+use rzlinkhelper_rs::{Configuration, Result};
+
+/// The sample config from the README.
+fn sample() -> Value {
+    let readme = std::fs::read_to_string(format!("{}/README.md", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let start = readme.find("```json5\n").unwrap() + "```json5\n".len();
+    let end = start + readme[start..].find("```").unwrap();
+    serde_json::from_str(&readme[start..end]).unwrap()
+}
+
+/// Load the sample config with `key` set to `value`, through a file as the pipeline does.
+fn load_with(key: &str, value: Value) -> Result<Configuration> {
+    let mut config = sample();
+    config[key] = value;
+    let path = std::env::temp_dir().join(format!("rzlinkhelper-config-{}-{}-{}.json",
+                                                 std::process::id(), key, config[key]));
+    std::fs::write(&path, config.to_string()).unwrap();
+    let config = Configuration::from_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    config
+}
+
+/// The sample config as it is, without the key `load_with` sets.
+fn sample_config() -> Configuration {
+    load_with("jobs", json!(8)).unwrap()
+}
+
+#[test]
+fn readme_sample_loads() {
+    let config = sample_config();
+    assert_eq!(config.compile_policy, CompilePolicy::FailFast);
+    assert_eq!(config.jobs, Some(8));
+}
+
+#[test]
+fn compile_policies() {
+    for (name, policy) in &[("fail-fast", CompilePolicy::FailFast), ("keep-going", CompilePolicy::KeepGoing),
+                            ("ignore", CompilePolicy::Ignore)] {
+        assert_eq!(load_with("compile_policy", json!(name)).unwrap().compile_policy, *policy);
+    }
+    assert!(load_with("compile_policy", json!("sometimes")).is_err());
+}
+
+#[test]
+fn build_backends() {
+    assert_eq!(sample_config().build_backend, BuildBackend::Make);
+    assert_eq!(load_with("build_backend", json!("ninja")).unwrap().build_backend, BuildBackend::Ninja);
+    assert!(load_with("build_backend", json!("bazel")).is_err());
+}
+
+#[test]
+fn export_formats() {
+    assert!(sample_config().export_formats.is_empty());
+    assert_eq!(load_with("export_formats", json!(["dot", "graphml", "gexf"])).unwrap().export_formats,
+               vec![Format::Dot, Format::Graphml, Format::Gexf]);
+    assert!(load_with("export_formats", json!(["svg"])).is_err());
+}
+
+#[test]
+fn graph_backends() {
+    assert_eq!(sample_config().graph_backend, GraphBackend::Plugin);
+    assert_eq!(load_with("graph_backend", json!("print-callgraph")).unwrap().graph_backend,
+               GraphBackend::PrintCallgraph);
+    assert_eq!("print-callgraph".parse::<GraphBackend>(), Ok(GraphBackend::PrintCallgraph));
+    assert!(load_with("graph_backend", json!("dumpcalls")).is_err());
+}
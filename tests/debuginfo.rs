@@ -0,0 +1,30 @@
+use rzlinkhelper_rs::debuginfo::{parse, Location};
+use rzlinkhelper_rs::demangle::demangle;
+
+fn location(file: &str, line: usize) -> Option<Location> {
+    Some(Location { file: String::from(file), line })
+}
+
+#[test]
+fn locations_from_subprograms() {
+    let ir = std::fs::read_to_string(format!("{}/tests/fixtures/debuginfo/module.ll", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let locations = parse(&ir);
+    assert_eq!(locations.get("main").cloned(), location("/work/app/src/main.c", 4));
+    assert_eq!(locations.get("_ZN2ns3addEii").cloned(), location("/usr/include/ns.h", 12));
+    assert_eq!(locations.get("weird name\"q").cloned(), location("/work/app/odd, name.c", 7));
+    assert_eq!(locations.get("no_debug"), None);
+    assert_eq!(locations.get("printf"), None);
+    assert_eq!(locations.len(), 3);
+}
+
+#[test]
+fn itanium_and_rust_names() {
+    assert_eq!(demangle("_ZN2ns3addEii").as_deref(), Some("ns::add(int, int)"));
+    assert_eq!(demangle("_ZNSt6vectorIiSaIiEE9push_backERKi").as_deref(),
+               Some("std::vector<int, std::allocator<int> >::push_back(int const&)"));
+    assert_eq!(demangle("_ZN4core3fmt5write17h0123456789abcdefE").as_deref(), Some("core::fmt::write"));
+    assert_eq!(demangle("_RNvCs1234_7mycrate3foo").as_deref(), Some("mycrate::foo"));
+    assert_eq!(demangle("main"), None);
+    assert_eq!(demangle("llvm.memcpy.p0i8.p0i8.i64"), None);
+    assert_eq!(demangle("_Zbogus"), None);
+}
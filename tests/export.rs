@@ -0,0 +1,44 @@
+use rzlinkhelper_rs::debuginfo::Location;
+use rzlinkhelper_rs::export::{Dot, Exporter, Gexf, Graph, GraphMl, Node};
+
+/// `main` calls an `operator<<` from a library, and `puts` from outside the project.
+fn graph(location: &Location) -> Graph<'_> {
+    Graph {
+        nodes: vec![
+            Node {
+                name: "_ZlsRSoPKc",
+                target: Some("/b/libio.so"),
+                uses: 1,
+                defined: true,
+                demangled: Some("operator<<(std::ostream&, char const*)"),
+                location: Some(location),
+            },
+            Node { name: "main", target: Some("/b/app"), uses: 0, defined: true, demangled: None, location: None },
+            Node { name: "puts", target: None, uses: 1, defined: false, demangled: None, location: None },
+        ],
+        edges: vec![(1, 0), (1, 2)],
+    }
+}
+
+fn check(exporter: &dyn Exporter, fixture: &str) {
+    let location = Location { file: String::from("/src/\"io\" & more.cpp"), line: 3 };
+    let expected = std::fs::read_to_string(
+        format!("{}/tests/fixtures/export/{}", env!("CARGO_MANIFEST_DIR"), fixture)).unwrap();
+    assert_eq!(exporter.export(&graph(&location)), expected);
+    assert_eq!(Some(exporter.extension()), fixture.rsplit('.').next());
+}
+
+#[test]
+fn dot() {
+    check(&Dot, "calls.dot");
+}
+
+#[test]
+fn graphml() {
+    check(&GraphMl, "calls.graphml");
+}
+
+#[test]
+fn gexf() {
+    check(&Gexf, "calls.gexf");
+}
@@ -0,0 +1,50 @@
+; ModuleID = 'app'
+source_filename = "llvm-link"
+target triple = "x86_64-pc-linux-gnu"
+
+@.str = private unnamed_addr constant [4 x i8] c"%d\0A\00", align 1
+
+define dso_local i32 @main() #0 !dbg !10 {
+entry:
+  %call = call i32 @_ZN2ns3addEii(i32 1, i32 2), !dbg !20
+  call void @"weird name\22q"(), !dbg !21
+  ret i32 %call, !dbg !22
+}
+
+define linkonce_odr dso_local i32 @_ZN2ns3addEii(i32 %a, i32 %b) #0 !dbg !15 !prof !30 {
+entry:
+  %add = add nsw i32 %a, %b
+  ret i32 %add
+}
+
+define internal void @"weird name\22q"() !dbg !17 {
+  ret void
+}
+
+define internal void @no_debug() {
+  ret void
+}
+
+declare i32 @printf(i8*, ...)
+
+attributes #0 = { noinline nounwind }
+
+!llvm.dbg.cu = !{!0, !5}
+!llvm.module.flags = !{!8, !9}
+
+!0 = distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: "clang", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "src/main.c", directory: "/work/app")
+!2 = !DIFile(filename: "/usr/include/ns.h", directory: "/work/app")
+!3 = !DIFile(filename: "odd, name.c", directory: "/work/app")
+!5 = distinct !DICompileUnit(language: DW_LANG_C_plus_plus_14, file: !2, producer: "clang", isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)
+!6 = !DISubroutineType(types: !7)
+!7 = !{null}
+!8 = !{i32 7, !"Dwarf Version", i32 5}
+!9 = !{i32 2, !"Debug Info Version", i32 3}
+!10 = distinct !DISubprogram(name: "main", scope: !1, file: !1, line: 4, type: !6, scopeLine: 5, spFlags: DISPFlagDefinition, unit: !0)
+!15 = distinct !DISubprogram(name: "add", linkageName: "_ZN2ns3addEii", scope: !2, file: !2, line: 12, type: !6, scopeLine: 13, flags: DIFlagPrototyped, spFlags: DISPFlagDefinition, unit: !5)
+!17 = distinct !DISubprogram(name: "weird", scope: !3, file: !3, line: 7, type: !6, scopeLine: 7, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition, unit: !0)
+!20 = !DILocation(line: 6, column: 15, scope: !10)
+!21 = !DILocation(line: 7, column: 3, scope: !10)
+!22 = !DILocation(line: 8, column: 3, scope: !10)
+!30 = !{!"function_entry_count", i64 1}
@@ -0,0 +1,47 @@
+use rzlinkhelper_rs::graph::{process_graph, read_graph, CallKind, GraphNode};
+
+/// Run `process_graph` over a copy of the call graph fixture `name`.
+fn process(name: &str) -> Vec<GraphNode> {
+    let fixture = format!("{}/tests/fixtures/callgraph/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let path = std::env::temp_dir().join(format!("rzlinkhelper-graph-{}-{}", std::process::id(), name));
+    std::fs::copy(&fixture, &path).unwrap();
+    process_graph(path.to_str().unwrap()).unwrap();
+    let nodes = read_graph(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    nodes
+}
+
+#[test]
+fn call_sites_are_counted_by_kind() {
+    let nodes = process("repeated.txt");
+    let main = nodes.iter().find(|x| x.name == "main").unwrap();
+    let edges = main.calls.iter()
+        .map(|x| (x.callee.as_deref(), x.count, x.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(edges, vec![
+        (Some("foo"), 2, CallKind::Direct),
+        (Some("printf"), 1, CallKind::External),
+        (None, 2, CallKind::Indirect),
+        (Some("llvm.memcpy.p0.p0.i64"), 1, CallKind::Intrinsic),
+    ]);
+    assert_eq!(main.callees().collect::<Vec<_>>(), vec!["foo", "printf", "llvm.memcpy.p0.p0.i64"]);
+    let printf = nodes.iter().find(|x| x.name == "printf").unwrap();
+    assert!(printf.calls.is_empty());
+}
+
+#[test]
+fn null_function_is_dropped() {
+    let nodes = process("indirect.txt");
+    let names = nodes.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["apply", "llvm.dbg.declare", "main"]);
+}
+
+#[test]
+fn only_bodies_are_defined() {
+    let nodes = process("indirect.txt");
+    let defined = nodes.iter().map(|x| (x.name.as_str(), x.defined)).collect::<Vec<_>>();
+    assert_eq!(defined, vec![("apply", true), ("llvm.dbg.declare", false), ("main", true)]);
+    let nodes = process("external.txt");
+    assert!(!nodes.iter().find(|x| x.name == "printf").unwrap().defined);
+    assert!(nodes.iter().find(|x| x.name == "callback").unwrap().defined);
+}
@@ -0,0 +1,50 @@
+use rzlinkhelper_rs::cmaker::{Collection, LinkScript, Object, Target, TargetKind};
+use rzlinkhelper_rs::{Configuration, Pipeline};
+
+/// The sample config from the README, with `llvm-link` failing whatever it is given.
+fn config() -> Configuration {
+    let readme = std::fs::read_to_string(format!("{}/README.md", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let start = readme.find("```json5\n").unwrap() + "```json5\n".len();
+    let end = start + readme[start..].find("```").unwrap();
+    let mut config: serde_json::Value = serde_json::from_str(&readme[start..end]).unwrap();
+    config["llvm_link_executable"] = serde_json::json!("false");
+    serde_json::from_value(config).unwrap()
+}
+
+#[test]
+fn failed_targets_lose_their_stale_modules() {
+    let dir = std::env::temp_dir().join(format!("rzlinkhelper-linking-{}", std::process::id()));
+    let pipeline = Pipeline::new(config(), &dir).unwrap().with_keep_going(true);
+    for x in &[pipeline.object_dir(), pipeline.graph_dir()] {
+        std::fs::create_dir_all(x).unwrap();
+    }
+    let collection = Collection {
+        objects: vec![Object {
+            abs_path: String::from("/b/main.o"),
+            name: String::from("main.o"),
+            defined_symbols: Vec::new(),
+            undefined_symbols: Vec::new(),
+        }],
+        scripts: vec![LinkScript {
+            abs_path: String::from("/b/app"),
+            target: Target {
+                name: String::from("app"),
+                abs_path: String::from("/b/app"),
+                dependencies: vec![String::from("/b/main.o")],
+                kind: TargetKind::Executable,
+            },
+        }],
+        compile: Vec::new(),
+    };
+    // left behind by an earlier run that linked the target
+    let module = pipeline.object_dir().join("%2Fb%2Fapp");
+    let graph = pipeline.graph_dir().join("%2Fb%2Fapp");
+    std::fs::write(&module, "stale").unwrap();
+    std::fs::write(&graph, "[]").unwrap();
+
+    let report = pipeline.link(&collection).unwrap();
+    assert_eq!(report.failed.iter().map(|x| x.target.as_str()).collect::<Vec<_>>(), vec!["/b/app"]);
+    assert!(std::fs::metadata(&module).is_err());
+    assert!(std::fs::metadata(&graph).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
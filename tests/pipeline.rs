@@ -0,0 +1,45 @@
+use rzlinkhelper_rs::{Configuration, Pipeline, Progress, Stage};
+
+fn failed_to_compile() -> Progress {
+    let mut progress = Progress::default();
+    for stage in Stage::ALL.iter().filter(|x| **x < Stage::Compile) {
+        progress.set(*stage, true);
+    }
+    progress.failed_objects = vec![String::from("/b/CMakeFiles/app.dir/main.cpp.o")];
+    progress
+}
+
+#[test]
+fn later_stages_keep_the_retry_list() {
+    let mut progress = failed_to_compile();
+    progress.reset_from(Stage::Link);
+    assert_eq!(progress.failed_objects, vec!["/b/CMakeFiles/app.dir/main.cpp.o"]);
+    assert!(progress.is_done(Stage::Cmaker));
+}
+
+#[test]
+fn recompiling_forgets_the_retry_list() {
+    for stage in &[Stage::Compile, Stage::Cmaker, Stage::Cmake] {
+        let mut progress = failed_to_compile();
+        progress.reset_from(*stage);
+        assert!(progress.failed_objects.is_empty(), "{} kept the retry list", stage);
+        assert!(Stage::ALL.iter().filter(|x| *x >= stage).all(|x| !progress.is_done(*x)));
+    }
+}
+
+/// The sample config from the README.
+fn config() -> Configuration {
+    let readme = std::fs::read_to_string(format!("{}/README.md", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let start = readme.find("```json5\n").unwrap() + "```json5\n".len();
+    let end = start + readme[start..].find("```").unwrap();
+    serde_json::from_str(&readme[start..end]).unwrap()
+}
+
+#[test]
+fn work_dir_is_absolute() {
+    let pipeline = Pipeline::new(config(), "project").unwrap();
+    assert_eq!(pipeline.work_dir(), std::env::current_dir().unwrap().join("project"));
+    assert!(pipeline.build_dir().is_absolute());
+    let pipeline = Pipeline::new(config(), "/src/project").unwrap();
+    assert_eq!(pipeline.object_dir(), std::path::Path::new("/src/project/rz_build/objects"));
+}
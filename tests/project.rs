@@ -0,0 +1,122 @@
+use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+
+use rzlinkhelper_rs::cmaker::{Collection, LinkScript, Object, Symbol, Target, TargetKind};
+use rzlinkhelper_rs::graph::{CallEdge, CallKind, GraphNode};
+use rzlinkhelper_rs::project::{self, ProjectGraph};
+use rzlinkhelper_rs::{Configuration, Pipeline};
+
+/// How the pipeline names the call graph files of targets.
+const FRAGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'`').add(b'/').add(b'\\');
+
+/// The sample config from the README.
+fn config() -> Configuration {
+    let readme = std::fs::read_to_string(format!("{}/README.md", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let start = readme.find("```json5\n").unwrap() + "```json5\n".len();
+    let end = start + readme[start..].find("```").unwrap();
+    serde_json::from_str(&readme[start..end]).unwrap()
+}
+
+fn object(path: &str, defined: &[&str], undefined: &[&str]) -> Object {
+    let symbols = |names: &[&str]| names.iter().map(|x| Symbol { name: String::from(*x), weak: false }).collect();
+    Object {
+        abs_path: String::from(path),
+        name: String::from(path.rsplit('/').next().unwrap()),
+        defined_symbols: symbols(defined),
+        undefined_symbols: symbols(undefined),
+    }
+}
+
+fn target(path: &str, kind: TargetKind, dependencies: &[&str]) -> LinkScript {
+    LinkScript {
+        abs_path: String::from(path),
+        target: Target {
+            name: String::from(path.rsplit('/').next().unwrap()),
+            abs_path: String::from(path),
+            dependencies: dependencies.iter().map(|x| String::from(*x)).collect(),
+            kind,
+        },
+    }
+}
+
+fn function(name: &str, defined: bool, calls: &[(&str, CallKind)]) -> GraphNode {
+    GraphNode {
+        name: String::from(name),
+        uses: 1,
+        address: 0,
+        calls: calls.iter()
+            .map(|(callee, kind)| CallEdge { callee: Some(String::from(*callee)), count: 1, kind: *kind })
+            .collect(),
+        defined,
+        demangled: None,
+        location: None,
+    }
+}
+
+/// `app` calls `foo` from `libfoo.so`, which it links; both call into libc.
+fn aggregate(with_symbols: bool) -> ProjectGraph {
+    let (main, library) = if with_symbols {
+        (object("/b/main.o", &["main"], &["foo", "printf"]), object("/b/foo.o", &["foo"], &["puts"]))
+    } else {
+        (object("/b/main.o", &[], &[]), object("/b/foo.o", &[], &[]))
+    };
+    let collection = Collection {
+        objects: vec![main, library],
+        scripts: vec![
+            target("/b/libfoo.so", TargetKind::SharedLibrary, &["/b/foo.o"]),
+            target("/b/app", TargetKind::Executable, &["/b/main.o", "/b/libfoo.so"]),
+        ],
+        compile: Vec::new(),
+    };
+    let dir = std::env::temp_dir().join(format!("rzlinkhelper-project-{}-{}", std::process::id(), with_symbols));
+    let pipeline = Pipeline::new(config(), &dir).unwrap();
+    std::fs::create_dir_all(pipeline.graph_dir()).unwrap();
+    let graphs = [
+        ("/b/libfoo.so", vec![
+            function("foo", true, &[("puts", CallKind::External)]),
+            function("puts", false, &[]),
+        ]),
+        ("/b/app", vec![
+            function("main", true, &[("foo", CallKind::External), ("printf", CallKind::External)]),
+            function("foo", false, &[]),
+            function("printf", false, &[]),
+        ]),
+    ];
+    for (target, nodes) in &graphs {
+        let path = pipeline.graph_dir().join(percent_encode(target.as_bytes(), FRAGMENT).to_string());
+        std::fs::write(path, serde_json::to_string(nodes).unwrap()).unwrap();
+    }
+    let project = project::aggregate(&pipeline, &collection).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    project
+}
+
+/// Nodes as their name and owning target, and edges by name.
+type Summary<'a> = (Vec<(&'a str, Option<&'a str>)>, Vec<(&'a str, &'a str)>);
+
+/// The nodes and edges of `project`, sorted.
+fn summary(project: &ProjectGraph) -> Summary<'_> {
+    let mut nodes = project.nodes.iter().map(|x| (x.name.as_str(), x.target.as_deref())).collect::<Vec<_>>();
+    nodes.sort_unstable();
+    let mut edges = project.nodes.iter()
+        .flat_map(|x| x.calls.iter().map(move |j| (x.name.as_str(), project.nodes[*j].name.as_str())))
+        .collect::<Vec<_>>();
+    edges.sort_unstable();
+    (nodes, edges)
+}
+
+fn expected() -> Summary<'static> {
+    (
+        vec![("foo", Some("/b/libfoo.so")), ("main", Some("/b/app")), ("printf", None), ("puts", None)],
+        vec![("foo", "puts"), ("main", "foo"), ("main", "printf")],
+    )
+}
+
+#[test]
+fn calls_into_linked_libraries_are_connected() {
+    assert_eq!(summary(&aggregate(true)), expected());
+}
+
+#[test]
+fn bodies_stand_in_for_missing_symbols() {
+    assert_eq!(summary(&aggregate(false)), expected());
+}
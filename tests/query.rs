@@ -0,0 +1,53 @@
+use rzlinkhelper_rs::export::{Graph, Node};
+use rzlinkhelper_rs::graph::GraphNode;
+use rzlinkhelper_rs::query::{answer, Query};
+
+fn node<'a>(name: &'a str, target: Option<&'a str>, defined: bool) -> Node<'a> {
+    Node { name, target, uses: 0, defined, demangled: None, location: None }
+}
+
+fn names(graph: &Graph, query: &Query) -> Vec<(String, Option<String>)> {
+    answer(graph, query).unwrap().functions.into_iter().map(|x| (x.name, x.target)).collect()
+}
+
+#[test]
+fn path_ends_at_the_closest_function() {
+    // `f` is defined in two libraries, the second reached by a shorter path
+    let graph = Graph {
+        nodes: vec![
+            node("main", Some("app"), true),
+            node("x", Some("app"), true),
+            node("y", Some("app"), true),
+            node("f", Some("liba"), true),
+            node("f", Some("libb"), true),
+        ],
+        edges: vec![(0, 1), (1, 2), (2, 3), (0, 4)],
+    };
+    let path = names(&graph, &Query::Path(String::from("main"), String::from("f")));
+    assert_eq!(path, vec![
+        (String::from("main"), Some(String::from("app"))),
+        (String::from("f"), Some(String::from("libb"))),
+    ]);
+}
+
+#[test]
+fn declarations_are_never_dead() {
+    let nodes = serde_json::from_str::<Vec<GraphNode>>(r#"[
+        {"name": "main", "uses": 1, "address": 1, "defined": true,
+         "calls": [{"callee": "printf", "count": 1, "kind": "external"},
+                   {"callee": "llvm.memcpy.p0.p0.i64", "count": 1, "kind": "intrinsic"}]},
+        {"name": "unused", "uses": 0, "address": 2, "defined": true, "calls": []},
+        {"name": "puts", "uses": 0, "address": 3, "defined": false, "calls": []},
+        {"name": "printf", "uses": 1, "address": 4, "defined": false, "calls": []}
+    ]"#).unwrap();
+    let graph = Graph::from_nodes(&nodes);
+    let dead = names(&graph, &Query::Dead(vec![String::from("main")]));
+    assert_eq!(dead, vec![(String::from("unused"), None)]);
+}
+
+#[test]
+fn unknown_functions_are_query_errors() {
+    let graph = Graph { nodes: vec![node("main", None, true)], edges: Vec::new() };
+    let e = answer(&graph, &Query::Callers(String::from("mian"))).unwrap_err();
+    assert_eq!((e.kind(), e.exit_code()), ("query", 5));
+}
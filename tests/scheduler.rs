@@ -0,0 +1,32 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rzlinkhelper_rs::scheduler::{run, Schedule, Status};
+
+#[test]
+fn independent_tasks_keep_going() {
+    let mut schedule = Schedule::new(3);
+    schedule.add_dependency(1, 0);
+    let outcome = run("testing", 2, &schedule, true, |i| match i {
+        0 => Err(rzlinkhelper_rs::Error::Parse(String::from("broken"))),
+        _ => Ok(()),
+    });
+    assert_eq!(outcome.status, vec![Status::Failed, Status::Skipped, Status::Succeeded]);
+    assert!(outcome.fatal.is_none());
+}
+
+#[test]
+fn a_panicking_task_does_not_hang_the_others() {
+    let started = AtomicUsize::new(0);
+    // the second worker waits for the panicking task to finish
+    let mut schedule = Schedule::new(3);
+    schedule.add_dependency(1, 0);
+    schedule.add_dependency(2, 0);
+    let result = catch_unwind(AssertUnwindSafe(|| run("testing", 2, &schedule, true, |i| {
+        started.fetch_add(1, Ordering::SeqCst);
+        panic!("task {} panicked", i);
+    })));
+    let panic = result.expect_err("the panic should reach the caller");
+    assert_eq!(panic.downcast_ref::<String>().map(String::as_str), Some("task 0 panicked"));
+    assert_eq!(started.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,63 @@
+use rzlinkhelper_rs::shell::{commands, lex, quote, words, Token};
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/shell/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e));
+    String::from(text.trim_end())
+}
+
+fn strings(x: &[&str]) -> Vec<String> {
+    x.iter().map(|x| String::from(*x)).collect()
+}
+
+#[test]
+fn spaces_in_paths() {
+    assert_eq!(commands(&fixture("spaces.txt")).unwrap(), vec![
+        strings(&["cd", "/home/u/my project/build"]),
+        strings(&["/usr/bin/c++", "-I/opt/with space/include", "-c", "../src/a b.cpp", "-o", "CMakeFiles/x.dir/a b.cpp.o"]),
+    ]);
+}
+
+#[test]
+fn quoted_defines() {
+    assert_eq!(words(&fixture("defines.txt")).unwrap(), strings(&[
+        "/usr/bin/cc", "-DNAME=\"value\"", "-DGREETING=\"hello world\"", "-DPATH=\"/a b\"", "-DEMPTY=",
+        "-c", "a.c", "-o", "a.o",
+    ]));
+}
+
+#[test]
+fn command_chains() {
+    let line = fixture("chain.txt");
+    let operators = lex(&line).unwrap().into_iter()
+        .filter_map(|x| match x {
+            Token::Operator(op) => Some(op),
+            Token::Word(_) => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(operators, vec!["&&", ";", "||", "|", "&"]);
+    assert_eq!(commands(&line).unwrap(), vec![
+        strings(&["cd", "/b"]),
+        strings(&["/usr/bin/c++", "-c", "a.cpp", "-o", "a.o"]),
+        strings(&["echo", "done; really"]),
+        strings(&["true"]),
+        strings(&["tee", "log"]),
+    ]);
+    assert!(words(&line).is_err());
+}
+
+#[test]
+fn unterminated_quote() {
+    assert!(lex(&fixture("unterminated.txt")).is_err());
+    assert!(lex("cc -c a.c \\").is_err());
+    assert!(lex("cc \"-DA=1").is_err());
+}
+
+#[test]
+fn quote_reads_back() {
+    assert_eq!(words("cc '' -c").unwrap(), vec!["cc", "", "-c"]);
+    for word in &["plain", "", "with space", "it's", "\"quoted\"", "a;b&&c", "$HOME", "back\\slash", "tab\there"] {
+        assert_eq!(words(&quote(word)).unwrap(), vec![String::from(*word)], "quoting {:?}", word);
+    }
+    assert_eq!(quote("-DNAME=1"), "-DNAME=1");
+}
@@ -0,0 +1,15 @@
+use rzlinkhelper_rs::error::Tool;
+use rzlinkhelper_rs::symbols;
+use rzlinkhelper_rs::Error;
+
+#[test]
+fn bitcode_needs_llvm_nm() {
+    let path = std::env::temp_dir().join(format!("rzlinkhelper-symbols-{}.bc", std::process::id()));
+    std::fs::write(&path, b"BC\xc0\xde\x35\x14\x00\x00").unwrap();
+    let result = symbols::read(&path, "/nonexistent/llvm-nm");
+    std::fs::remove_file(&path).unwrap();
+    match result {
+        Err(e @ Error::Tool { tool: Tool::LlvmNm, .. }) => assert_eq!(e.exit_code(), 30),
+        x => panic!("expected an llvm-nm failure, got {:?}", x),
+    }
+}
@@ -0,0 +1,116 @@
+use rzlinkhelper_rs::cmaker::{Collection, LinkScript, Object, Symbol, Target, TargetKind};
+use rzlinkhelper_rs::targets::TargetGraph;
+
+fn symbols(names: &[&str]) -> Vec<Symbol> {
+    names.iter().map(|x| Symbol { name: String::from(*x), weak: false }).collect()
+}
+
+fn object(path: &str, defined: &[&str], undefined: &[&str]) -> Object {
+    Object {
+        abs_path: String::from(path),
+        name: String::from(path.rsplit('/').next().unwrap()),
+        defined_symbols: symbols(defined),
+        undefined_symbols: symbols(undefined),
+    }
+}
+
+fn target(name: &str, kind: TargetKind, dependencies: &[&str]) -> LinkScript {
+    let abs_path = format!("/b/{}", name);
+    LinkScript {
+        abs_path: abs_path.clone(),
+        target: Target {
+            name: String::from(name),
+            abs_path,
+            dependencies: dependencies.iter().map(|x| String::from(*x)).collect(),
+            kind,
+        },
+    }
+}
+
+/// `a` and `b` link each other, `app` links `a` and an object nobody builds, `c` stands apart.
+fn cyclic() -> Collection {
+    Collection {
+        objects: vec![object("/b/a.o", &[], &[]), object("/b/b.o", &[], &[]), object("/b/main.o", &[], &[]),
+                      object("/b/c.o", &[], &[])],
+        scripts: vec![
+            target("a", TargetKind::SharedLibrary, &["/b/a.o", "/b/b"]),
+            target("b", TargetKind::SharedLibrary, &["/b/b.o", "/b/a"]),
+            target("app", TargetKind::Executable, &["/b/main.o", "/b/a", "/b/missing.o"]),
+            target("c", TargetKind::StaticLibrary, &["/b/c.o"]),
+        ],
+        compile: Vec::new(),
+    }
+}
+
+#[test]
+fn cycles_and_their_dependents_are_found() {
+    let collection = cyclic();
+    let graph = TargetGraph::new(&collection);
+    let analysis = graph.analyze();
+    assert_eq!(analysis.cycles, vec![vec![0, 1, 0]]);
+    assert_eq!(analysis.blocked, vec![2]);
+    assert_eq!(analysis.unknown, vec![(2, "/b/missing.o")]);
+}
+
+#[test]
+fn cycles_fail_the_check() {
+    let collection = cyclic();
+    let message = TargetGraph::new(&collection).check(false).unwrap_err().to_string();
+    assert!(message.contains("dependency cycle: a -> b -> a"), "{}", message);
+    assert!(message.contains("target app can never become ready"), "{}", message);
+    assert!(!message.contains("missing.o"), "{}", message);
+    assert!(!message.contains("target c"), "{}", message);
+}
+
+#[test]
+fn unknown_inputs_fail_only_strict_checks() {
+    let collection = Collection {
+        objects: vec![object("/b/main.o", &[], &[])],
+        scripts: vec![target("app", TargetKind::Executable, &["/b/main.o", "/usr/lib/libm.so"])],
+        compile: Vec::new(),
+    };
+    let graph = TargetGraph::new(&collection);
+    assert!(graph.analyze().cycles.is_empty());
+    assert!(graph.check(false).is_ok());
+    let e = graph.check(true).unwrap_err();
+    assert_eq!(e.exit_code(), 4);
+    assert!(e.to_string().contains("target app depends on unknown input /usr/lib/libm.so"), "{}", e);
+}
+
+/// `app` links `libutil.a`, whose members resolve each other, and `libraw.a`, which has no symbols.
+fn archives() -> Collection {
+    let mut main = object("/b/main.o", &["main"], &["f"]);
+    main.undefined_symbols.push(Symbol { name: String::from("w"), weak: true });
+    Collection {
+        objects: vec![
+            main,
+            object("/b/u1.o", &["f"], &["g"]),
+            object("/b/u2.o", &["g"], &[]),
+            object("/b/u3.o", &["h"], &["g"]),
+            object("/b/u4.o", &["w"], &[]),
+            object("/b/r.o", &[], &[]),
+        ],
+        scripts: vec![
+            target("libutil.a", TargetKind::StaticLibrary, &["/b/u1.o", "/b/u2.o", "/b/u3.o", "/b/u4.o"]),
+            target("libraw.a", TargetKind::StaticLibrary, &["/b/r.o"]),
+            target("app", TargetKind::Executable, &["/b/main.o", "/b/libutil.a", "/b/libraw.a"]),
+        ],
+        compile: Vec::new(),
+    }
+}
+
+#[test]
+fn executables_pull_the_members_they_need() {
+    let collection = archives();
+    let graph = TargetGraph::new(&collection);
+    // u1 for `f`, then u2 for the `g` u1 needs; nothing needs `h`, and `w` is only weakly referenced
+    assert_eq!(graph.inputs(2), vec!["/b/libraw.a", "/b/main.o", "/b/u1.o", "/b/u2.o"]);
+}
+
+#[test]
+fn static_libraries_keep_all_their_members() {
+    let collection = archives();
+    let graph = TargetGraph::new(&collection);
+    assert_eq!(graph.inputs(0), vec!["/b/u1.o", "/b/u2.o", "/b/u3.o", "/b/u4.o"]);
+    assert_eq!(graph.inputs(1), vec!["/b/r.o"]);
+}
@@ -0,0 +1,23 @@
+use std::path::Path;
+
+use rzlinkhelper_rs::command::CompileCommand;
+use rzlinkhelper_rs::trace;
+
+const COMPILERS: &[&str] = &["/usr/bin/c++", "/usr/bin/cc"];
+
+#[test]
+fn compile_lines_name_their_objects() {
+    let log = std::fs::read_to_string(format!("{}/tests/fixtures/trace/remake.log", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let collection = trace::parse(&log, Path::new("/b"), COMPILERS).unwrap();
+    let objects = collection.objects.iter().map(|x| x.abs_path.as_str()).collect::<Vec<_>>();
+    assert_eq!(objects, vec![
+        "/b/app/CMakeFiles/app.dir/main.cpp.o",
+        "/b/CMakeFiles/util.dir/util.c.o",
+        "/b/lib/CMakeFiles/lib.dir/lib.c.o",
+    ]);
+    // the compile stage must find each object under the same name
+    let compiled = collection.compile.iter()
+        .map(|x| CompileCommand::parse(x, COMPILERS).unwrap().object())
+        .collect::<Vec<_>>();
+    assert_eq!(compiled, objects);
+}